@@ -282,10 +282,33 @@ pub struct SQSChannelBinding {}
 #[serde(rename_all = "camelCase")]
 pub struct STOMPChannelBinding {}
 
-/// This object MUST NOT contain any properties. Its name is reserved for future use.
+/// This object contains information about the channel representation in Redis.
+/// The official binding is reserved for future use, these properties are an extension of this generator.
+///
+/// # Examples
+///
+/// ```yaml
+/// channels:
+///   orders:
+///     bindings:
+///       redis:
+///         type: stream
+///         maxLen: 10000
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
-pub struct RedisChannelBinding {}
+pub struct RedisChannelBinding {
+    /// `pubsub` (default) for Pub/Sub channels or `stream` for Redis Streams.
+    /// A channel can also be marked as stream with the `x-redis-stream: true` extension.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    /// Approximate number of entries a stream is trimmed to when adding entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<u64>,
+    /// The version of this binding. If omitted, "latest" MUST be assumed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binding_version: Option<String>,
+}
 
 /// This object MUST NOT contain any properties. Its name is reserved for future use.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use super::{
     message_binding::MessageBinding, Channel, ChannelBinding, CorrelationId, Message, MessageTrait,
-    OperationBinding, OperationTrait, Parameter, ReferenceOr, Schema, SecurityScheme, Server,
+    OperationBinding, OperationTrait, Parameter, ReferenceOr, SecurityScheme, Server,
     ServerBinding,
 };
 
@@ -186,8 +186,10 @@ use super::{
 pub struct Components {
     /// An object to hold reusable
     /// [Schema Objects][crate::Schema].
+    /// Kept as json, as schemas of another `schemaFormat` (e.g. RAML types)
+    /// are only normalized in the payloads referencing them.
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
-    pub schemas: IndexMap<String, ReferenceOr<Schema>>,
+    pub schemas: IndexMap<String, serde_json::Value>,
     /// An object to hold reusable
     /// [Message Objects][crate::Message].
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
@@ -2,7 +2,8 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use super::{
-    CorrelationId, Example, ExternalDocumentation, MessageBinding, ReferenceOr, Schema, Tag,
+    CorrelationId, Example, ExternalDocumentation, MessageBinding, MessageTrait, ReferenceOr,
+    Schema, Tag,
 };
 
 /// Describes a message received on a given channel and operation.
@@ -220,6 +221,12 @@ pub struct Message {
     /// A map where the keys describe the name of
     /// the protocol and the values describe protocol-specific definitions for the message.
     pub bindings: Option<ReferenceOr<MessageBinding>>,
+    /// A list of traits to apply to the message object.
+    /// Traits MUST be merged into the message object using the
+    /// [JSON Merge Patch](https://tools.ietf.org/html/rfc7386)
+    /// algorithm in the same order they are defined here.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub traits: Vec<ReferenceOr<MessageTrait>>,
     /// An array with examples of valid message objects.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub examples: Vec<Example>, // TODO try to parse better
@@ -182,6 +182,10 @@ pub struct AMQPMessageBinding {
     /// Application-specific message type.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub message_type: Option<String>,
+    /// Schema of the queue the reply to this message is sent to, used by the
+    /// [rpc examples](https://github.com/asyncapi/spec/tree/v2.6.0/examples) of AsyncAPI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Schema>,
     /// The version of this binding. If omitted, "latest" MUST be assumed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub binding_version: Option<String>,
@@ -250,6 +250,75 @@ pub struct NATSOperationBinding {
 
     #[serde(rename(deserialize = "x-streamname", serialize = "streamname"))]
     pub streamname: Option<String>,
+    /// The JetStream consumer reading the stream, a crustagen extension.
+    #[serde(
+        rename(deserialize = "x-consumer", serialize = "consumer"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub consumer: Option<NATSConsumer>,
+    /// The settings the stream is created with if it does not exist, a crustagen extension.
+    #[serde(
+        rename(deserialize = "x-stream", serialize = "stream"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stream: Option<NATSStream>,
+}
+
+/// The durable JetStream consumer of an `x-streamname` operation, see
+/// [consumers](https://docs.nats.io/nats-concepts/jetstream/consumers).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NATSConsumer {
+    /// The durable name of the consumer, the channel name if omitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Whether messages are fetched (`pull`) or sent to the service (`push`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// `explicit`, `all` or `none`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ack_policy: Option<String>,
+    /// `all`, `last`, `new`, `last_per_subject` or `by_start_time`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deliver_policy: Option<String>,
+    /// The RFC 3339 time the `by_start_time` deliver policy starts at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opt_start_time: Option<String>,
+    /// How often a message is delivered before it is given up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_deliver: Option<i64>,
+    /// Milliseconds the server waits for the acknowledgement before delivering a message again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ack_wait: Option<u64>,
+    /// The subjects of the stream the consumer reads, all subjects if omitted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filter_subjects: Vec<String>,
+    /// The number of messages a pull consumer fetches at once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_batch: Option<usize>,
+    /// Milliseconds a pull request waits for messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+/// The stream of an `x-streamname` operation, see
+/// [streams](https://docs.nats.io/nats-concepts/jetstream/streams).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NATSStream {
+    /// `limits`, `interest` or `workqueue`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention: Option<String>,
+    /// `file` or `memory`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+    /// Milliseconds messages are kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_msgs: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<i64>,
 }
 
 /// This object MUST NOT contain any properties. Its name is reserved for future use.
@@ -350,9 +419,36 @@ pub struct SQSOperationBinding {}
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct STOMPOperationBinding {}
 
-/// This object MUST NOT contain any properties. Its name is reserved for future use.
+/// This object contains information about the consumer of a Redis stream.
+/// The official binding is reserved for future use, these properties are an extension of this generator.
+///
+/// # Examples
+///
+/// ```yaml
+/// channels:
+///   orders:
+///     publish:
+///       bindings:
+///         redis:
+///           consumerGroup: order-service
+///           minIdleTime: 30000
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
-pub struct RedisOperationBinding {}
+#[serde(rename_all = "camelCase")]
+pub struct RedisOperationBinding {
+    /// Consumer group reading the stream, defaults to the id of the operation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consumer_group: Option<String>,
+    /// Name of the consumer within its group, defaults to a name unique to the service instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consumer_name: Option<String>,
+    /// Milliseconds an entry stays pending before another consumer of the group claims it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_idle_time: Option<u64>,
+    /// The version of this binding. If omitted, "latest" MUST be assumed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binding_version: Option<String>,
+}
 
 /// This object MUST NOT contain any properties. Its name is reserved for future use.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
@@ -57,6 +57,10 @@ pub fn render_write_template(
             std::process::exit(1);
         }
     };
+    // protocol specific templates render to nothing for other protocols
+    if render.trim().is_empty() {
+        return;
+    }
     if output_path.ends_with(".env") {
         let mut lines: Vec<&str> = render.split('\n').collect();
         lines.retain(|&x| x.trim() != "");
@@ -7,7 +7,8 @@ pub static TEMPLATE_FUNCTIONS: &[(&str, Func)] = &[
     ("replace", replace as Func),
 ];
 
-/// TODO: descriptive comment needed about what function does!!
+/// checks if the nested keys `args[1..]` exist in the map `args[0]` and lead to a value that is not nil
+/// Example: `key_exists . "original_operation" "bindings" "nats" "queue"`
 pub fn key_exists(
     args: &[gtmpl_value::Value],
 ) -> Result<gtmpl_value::Value, gtmpl_value::FuncError> {
@@ -19,7 +20,7 @@ pub fn key_exists(
     }
     let map = args[0].clone();
     if args.len() == 1 {
-        return Ok(gtmpl_value::Value::Bool(true));
+        return Ok(gtmpl_value::Value::Bool(map != gtmpl_value::Value::Nil));
     }
     let keys = args[1..].to_vec();
     // check if keys is empty
@@ -34,14 +35,13 @@ pub fn key_exists(
         match key {
             gtmpl_value::Value::String(s) => {
                 let res: Result<gtmpl_value::Value, gtmpl_value::FuncError> = match map {
-                    gtmpl_value::Value::Object(o) => {
+                    gtmpl_value::Value::Object(o) => match o.get(&s) {
                         // call again with rest of keys
-                        key_exists(
-                            vec![vec![o.get(&s).unwrap().clone()], rest_keys]
-                                .concat()
-                                .as_slice(),
-                        )
-                    }
+                        Some(value) => {
+                            key_exists(vec![vec![value.clone()], rest_keys].concat().as_slice())
+                        }
+                        None => Ok(gtmpl_value::Value::Bool(false)),
+                    },
                     _ => Ok(gtmpl_value::Value::Bool(false)),
                 };
                 return res;
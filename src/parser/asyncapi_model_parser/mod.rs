@@ -4,6 +4,7 @@ use crate::asyncapi_model::AsyncAPI;
 use crate::parser::common::read_json_or_yaml_to_value;
 mod common;
 mod preprocessor;
+mod schema_formats;
 mod validator;
 
 pub fn parse_spec_to_model(specs_dir: &Path) -> Result<AsyncAPI, Box<dyn std::error::Error>> {
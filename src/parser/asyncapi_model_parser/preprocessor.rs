@@ -1,11 +1,13 @@
 use serde_json::json;
 use std::{collections::HashSet, panic};
 
+use super::schema_formats::{normalize_schema_formats, validation_schema};
 use crate::parser::common::{self, validate_identifier_string};
 
 pub fn preprocess_schema(spec: serde_json::Value) -> serde_json::Value {
     let with_message_names = fill_message_and_payload_names(spec.clone(), spec, false, false, None);
-    let resolved_refs = resolve_refs(with_message_names.clone(), with_message_names);
+    let normalized_schemas = normalize_schema_formats(with_message_names);
+    let resolved_refs = resolve_refs(normalized_schemas.clone(), normalized_schemas);
     let with_payload_schemas = duplicate_payload_schemas(resolved_refs.clone(), resolved_refs);
     let mut seen = HashSet::new();
     sanitize_operation_ids_and_check_duplicate(
@@ -104,16 +106,24 @@ pub fn duplicate_payload_schemas(
 ) -> serde_json::Value {
     match json {
         serde_json::Value::Object(map) => {
+            let schema_format = map.get("schemaFormat").cloned();
             let mut new_map = serde_json::Map::new();
             for (key, value) in map {
                 if key == "payload" {
                     if let serde_json::Value::Object(schema) = value {
                         // insert schema as json string
+                        let json_schema = validation_schema(
+                            schema_format.as_ref(),
+                            serde_json::Value::Object(schema.clone()),
+                        );
                         new_map.insert(
                             "schema".into(),
-                            serde_json::Value::String(serde_json::to_string(&schema).unwrap()),
+                            serde_json::Value::String(serde_json::to_string(&json_schema).unwrap()),
                         );
                         new_map.insert("payload".into(), serde_json::Value::Object(schema.clone()));
+                    } else {
+                        // e.g. a .proto definition or a primitive avro schema
+                        new_map.insert(key, value);
                     }
                 } else {
                     let new_value = duplicate_payload_schemas(value, root_json.clone());
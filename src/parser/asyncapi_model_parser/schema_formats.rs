@@ -0,0 +1,692 @@
+use serde_json::{json, Map, Value};
+
+// payload schema formats which are normalized to the json schema the `asyncapi_model::schema` module expects
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SchemaFormat {
+    OpenApi,
+    Raml,
+}
+
+impl SchemaFormat {
+    fn parse(schema_format: &str) -> Option<Self> {
+        let media_type = schema_format
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        match media_type.as_str() {
+            "application/vnd.oai.openapi"
+            | "application/vnd.oai.openapi+json"
+            | "application/vnd.oai.openapi+yaml" => Some(SchemaFormat::OpenApi),
+            "application/raml+yaml" => Some(SchemaFormat::Raml),
+            _ => None,
+        }
+    }
+}
+
+/// rewrites OpenAPI 3 and RAML 1.0 payload schemas to json schema
+/// every payload gets a normalized copy of the component schemas it references inlined,
+/// so component schemas shared with payloads of other formats stay untouched
+pub fn normalize_schema_formats(mut spec: serde_json::Value) -> serde_json::Value {
+    let mut payloads: Vec<(String, SchemaFormat)> = vec![];
+    find_payloads(&spec, "", &mut payloads);
+
+    for (pointer, format) in payloads {
+        let mut payload = match spec.pointer(&pointer) {
+            Some(payload) => payload.clone(),
+            None => continue,
+        };
+        let mut normalizer = Normalizer {
+            spec: &spec,
+            format,
+            resolving: vec![],
+        };
+        normalizer.normalize(&mut payload);
+        if let Some(target) = spec.pointer_mut(&pointer) {
+            *target = payload;
+        }
+    }
+    spec
+}
+
+/// the json schema the payload of a message is validated with,
+/// payloads normalized from another `schemaFormat` allow null with a type array instead of `nullable`
+pub fn validation_schema(schema_format: Option<&Value>, payload: Value) -> Value {
+    match schema_format
+        .and_then(Value::as_str)
+        .and_then(SchemaFormat::parse)
+    {
+        Some(_) => nullable_to_type_array(payload),
+        None => payload,
+    }
+}
+
+// `nullable` is no json schema keyword, so the schema used for validation allows null with a type array instead
+fn nullable_to_type_array(json: Value) -> Value {
+    match json {
+        Value::Object(map) => {
+            let nullable = map.get("nullable") == Some(&Value::Bool(true));
+            let mut new_map = Map::new();
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("nullable", Value::Bool(_)) => (),
+                    ("type", Value::String(schema_type)) if nullable => {
+                        new_map.insert(key, json!([schema_type, "null"]));
+                    }
+                    (_, value) => {
+                        new_map.insert(key, nullable_to_type_array(value));
+                    }
+                }
+            }
+            Value::Object(new_map)
+        }
+        Value::Array(array) => {
+            Value::Array(array.into_iter().map(nullable_to_type_array).collect())
+        }
+        _ => json,
+    }
+}
+
+// normalizes the schemas of one payload, the schemas it references are normalized with the format of the payload
+struct Normalizer<'a> {
+    spec: &'a Value,
+    format: SchemaFormat,
+    // the references being inlined, a recursive reference is kept as it is
+    resolving: Vec<String>,
+}
+
+impl Normalizer<'_> {
+    fn normalize(&mut self, schema: &mut Value) {
+        match self.format {
+            SchemaFormat::OpenApi => self.normalize_openapi_schema(schema),
+            SchemaFormat::Raml => self.normalize_raml_type(schema),
+        }
+    }
+
+    // a normalized copy of the schema `reference` points to, `None` if it does not exist or is recursive
+    fn resolve(&mut self, reference: &str) -> Option<Value> {
+        let pointer = reference.strip_prefix('#')?;
+        if self
+            .resolving
+            .iter()
+            .any(|resolving| resolving == reference)
+        {
+            return None;
+        }
+        let mut schema = self.spec.pointer(pointer)?.clone();
+        self.resolving.push(reference.to_string());
+        self.normalize(&mut schema);
+        self.resolving.pop();
+        Some(schema)
+    }
+
+    // replaces a `$ref` schema with a normalized copy of the schema it references, false if it is no reference
+    fn inline_reference(&mut self, schema: &mut Value) -> bool {
+        let reference = match schema.get("$ref") {
+            Some(Value::String(reference)) => reference.clone(),
+            _ => return false,
+        };
+        if let Some(resolved) = self.resolve(&reference) {
+            *schema = resolved;
+        }
+        true
+    }
+
+    // normalizes all subschemas of a schema
+    fn normalize_subschemas(&mut self, map: &mut Map<String, Value>) {
+        for key in ["items", "not", "additionalProperties"] {
+            if let Some(subschema) = map.get_mut(key) {
+                self.normalize(subschema);
+            }
+        }
+        if let Some(Value::Object(properties)) = map.get_mut("properties") {
+            for property in properties.values_mut() {
+                self.normalize(property);
+            }
+        }
+        for key in ["allOf", "oneOf", "anyOf"] {
+            if let Some(Value::Array(subschemas)) = map.get_mut(key) {
+                for subschema in subschemas {
+                    self.normalize(subschema);
+                }
+            }
+        }
+    }
+
+    // OpenAPI 3.0 uses `nullable`, boolean exclusive bounds and a discriminator object,
+    // OpenAPI 3.1 allows type arrays like json schema, which are turned into `nullable` as well
+    fn normalize_openapi_schema(&mut self, schema: &mut Value) {
+        if self.inline_reference(schema) {
+            return;
+        }
+        let map = match schema {
+            Value::Object(map) => map,
+            _ => return,
+        };
+
+        if let Some(Value::Array(types)) = map.get("type").cloned() {
+            let non_null_types: Vec<Value> = types
+                .iter()
+                .filter(|schema_type| schema_type.as_str() != Some("null"))
+                .cloned()
+                .collect();
+            if non_null_types.len() < types.len() {
+                map.insert("nullable".to_string(), json!(true));
+            }
+            match non_null_types.as_slice() {
+                [single_type] => {
+                    map.insert("type".to_string(), single_type.clone());
+                }
+                _ => {
+                    map.remove("type");
+                    let one_of = non_null_types
+                        .into_iter()
+                        .map(|schema_type| json!({ "type": schema_type }))
+                        .collect();
+                    map.insert("oneOf".to_string(), Value::Array(one_of));
+                }
+            }
+        }
+
+        // `exclusiveMinimum: true` with `minimum: 5` becomes `exclusiveMinimum: 5`
+        for (exclusive, bound) in [
+            ("exclusiveMinimum", "minimum"),
+            ("exclusiveMaximum", "maximum"),
+        ] {
+            if let Some(Value::Bool(is_exclusive)) = map.get(exclusive).cloned() {
+                map.remove(exclusive);
+                if is_exclusive {
+                    if let Some(bound_value) = map.remove(bound) {
+                        map.insert(exclusive.to_string(), bound_value);
+                    }
+                }
+            }
+        }
+
+        // the discriminator object is reduced to its property name, the mapping lists the variants if there is no oneOf
+        if let Some(Value::Object(discriminator)) = map.get("discriminator").cloned() {
+            map.remove("discriminator");
+            if let Some(Value::String(property_name)) = discriminator.get("propertyName") {
+                map.insert("discriminator".to_string(), json!(property_name));
+            }
+            if let Some(Value::Object(mapping)) = discriminator.get("mapping") {
+                if !map.contains_key("oneOf") && !map.contains_key("anyOf") {
+                    let one_of = mapping
+                        .values()
+                        .filter_map(|target| target.as_str())
+                        .map(|target| match target.contains('/') {
+                            true => json!({ "$ref": target }),
+                            // plain schema names reference component schemas
+                            false => json!({ "$ref": format!("#/components/schemas/{}", target) }),
+                        })
+                        .collect();
+                    map.insert("oneOf".to_string(), Value::Array(one_of));
+                }
+            }
+        }
+
+        self.normalize_subschemas(map);
+    }
+
+    // RAML 1.0 data types default to objects or strings, use type expressions like `string[]` or `string | nil`
+    // and mark optional properties with a trailing `?` or `required: false`
+    fn normalize_raml_type(&mut self, schema: &mut Value) {
+        if let Value::String(type_expression) = schema {
+            *schema = json!({ "type": type_expression.clone() });
+        }
+        if self.inline_reference(schema) {
+            return;
+        }
+        let map = match schema {
+            Value::Object(map) => map,
+            _ => return,
+        };
+
+        match map.get("type").cloned() {
+            Some(Value::String(type_expression)) => {
+                map.remove("type");
+                let normalized = self.raml_type_expression(&type_expression);
+                if normalized.get("$ref").is_some() {
+                    // facets next to a reference are dropped, like for json schema refs, a union with `nil` stays nullable
+                    let nullable = normalized.get("nullable").cloned();
+                    *schema = normalized;
+                    self.inline_reference(schema);
+                    if let (Some(nullable), Value::Object(map)) = (nullable, schema) {
+                        map.insert("nullable".to_string(), nullable);
+                    }
+                    return;
+                }
+                if let Value::Object(normalized) = normalized {
+                    map.extend(normalized);
+                }
+            }
+            // multiple inheritance
+            Some(Value::Array(parents)) => {
+                map.remove("type");
+                let all_of = parents
+                    .iter()
+                    .filter_map(|parent| parent.as_str())
+                    .map(|parent| self.raml_type_expression(parent))
+                    .collect();
+                map.insert("allOf".to_string(), Value::Array(all_of));
+            }
+            Some(Value::Object(_)) => {
+                if let Some(inline_type) = map.get_mut("type") {
+                    self.normalize_raml_type(inline_type);
+                }
+            }
+            _ => {
+                let default_type = match map.contains_key("properties") {
+                    true => "object",
+                    false if map.contains_key("items") => "array",
+                    false => "string",
+                };
+                map.insert("type".to_string(), json!(default_type));
+            }
+        }
+
+        if let Some(Value::String(format)) = map.get("format") {
+            let format = match format.as_str() {
+                "int8" | "int16" | "int32" => Some("int32"),
+                "int" | "int64" | "long" => Some("int64"),
+                _ => None,
+            };
+            if let Some(format) = format {
+                map.insert("format".to_string(), json!(format));
+            }
+        }
+
+        if let Some(Value::Object(properties)) = map.remove("properties") {
+            let mut normalized_properties = Map::new();
+            let mut required: Vec<Value> = vec![];
+            for (key, mut property) in properties {
+                let (name, mut optional) = match key.strip_suffix('?') {
+                    Some(name) => (name.to_string(), true),
+                    None => (key.clone(), false),
+                };
+                if let Value::Object(property_map) = &mut property {
+                    if let Some(Value::Bool(is_required)) = property_map.remove("required") {
+                        optional = !is_required;
+                    }
+                }
+                self.normalize_raml_type(&mut property);
+                match optional {
+                    // optional properties become Options in the generated types
+                    true => {
+                        if let Value::Object(property_map) = &mut property {
+                            property_map.insert("nullable".to_string(), json!(true));
+                        }
+                    }
+                    false => required.push(json!(name)),
+                }
+                normalized_properties.insert(name, property);
+            }
+            map.insert(
+                "properties".to_string(),
+                Value::Object(normalized_properties),
+            );
+            if !required.is_empty() {
+                map.insert("required".to_string(), Value::Array(required));
+            }
+        }
+
+        if let Some(items) = map.get_mut("items") {
+            self.normalize_raml_type(items);
+        }
+        for key in ["allOf", "oneOf", "anyOf"] {
+            if let Some(Value::Array(subschemas)) = map.get_mut(key) {
+                for subschema in subschemas {
+                    self.normalize_raml_type(subschema);
+                }
+            }
+        }
+    }
+
+    // translates a RAML type expression (e.g. `string`, `Address[]`, `string | nil`, `datetime`) to a json schema
+    fn raml_type_expression(&mut self, type_expression: &str) -> Value {
+        let type_expression = type_expression.trim();
+        let variants: Vec<&str> = type_expression
+            .split('|')
+            .map(|variant| variant.trim())
+            .collect();
+        if variants.len() > 1 {
+            let non_nil_variants: Vec<&str> = variants
+                .iter()
+                .filter(|variant| **variant != "nil")
+                .copied()
+                .collect();
+            let mut normalized = match non_nil_variants.as_slice() {
+                [single_variant] => self.raml_type_expression(single_variant),
+                _ => json!({
+                    "oneOf": non_nil_variants
+                        .iter()
+                        .map(|variant| self.raml_type_expression(variant))
+                        .collect::<Vec<Value>>()
+                }),
+            };
+            if non_nil_variants.len() < variants.len() {
+                if let Value::Object(map) = &mut normalized {
+                    map.insert("nullable".to_string(), json!(true));
+                }
+            }
+            return normalized;
+        }
+
+        let type_expression = type_expression
+            .trim_start_matches('(')
+            .trim_end_matches(')');
+        if let Some(item_type) = type_expression.strip_suffix("[]") {
+            return json!({ "type": "array", "items": self.raml_type_expression(item_type) });
+        }
+        match type_expression {
+            "string" | "number" | "integer" | "boolean" | "object" | "array" => {
+                json!({ "type": type_expression })
+            }
+            "date-only" => json!({ "type": "string", "format": "date" }),
+            "datetime" | "datetime-only" => json!({ "type": "string", "format": "date-time" }),
+            "time-only" => json!({ "type": "string" }),
+            "file" => json!({ "type": "string", "format": "binary" }),
+            "nil" => json!({ "type": "null" }),
+            "any" => json!({}),
+            // user defined types are expected in the component schemas
+            type_name => {
+                json!({ "$ref": format!("#/components/schemas/{}", type_name) })
+            }
+        }
+    }
+}
+
+// collects the json pointers of all payloads of messages with a schema format that needs to be normalized
+fn find_payloads(json: &Value, pointer: &str, pending: &mut Vec<(String, SchemaFormat)>) {
+    match json {
+        Value::Object(map) => {
+            if let (Some(Value::String(schema_format)), Some(_)) =
+                (map.get("schemaFormat"), map.get("payload"))
+            {
+                if let Some(format) = SchemaFormat::parse(schema_format) {
+                    pending.push((format!("{}/payload", pointer), format));
+                }
+            }
+            for (key, value) in map {
+                let escaped_key = key.replace('~', "~0").replace('/', "~1");
+                find_payloads(value, &format!("{}/{}", pointer, escaped_key), pending);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.iter().enumerate() {
+                find_payloads(value, &format!("{}/{}", pointer, index), pending);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, process::Command};
+
+    use super::*;
+    use crate::{
+        asyncapi_model::AsyncAPI, generator::render_write_all_embedded_templates,
+        parser::asyncapi_model_parser::preprocessor::preprocess_schema,
+        parser::common::read_json_or_yaml_to_value, template_context::create_template_context,
+    };
+
+    const OPENAPI: &str = "application/vnd.oai.openapi;version=3.0.0";
+    const RAML: &str = "application/raml+yaml;version=1.0";
+
+    // a spec with a single message of `schema_format` and the given component schemas
+    fn spec_with_payload(schema_format: &str, payload: Value, schemas: Value) -> Value {
+        json!({
+            "channels": {
+                "test": {
+                    "publish": {
+                        "message": { "schemaFormat": schema_format, "payload": payload }
+                    }
+                }
+            },
+            "components": { "schemas": schemas }
+        })
+    }
+
+    fn normalized_payload(schema_format: &str, payload: Value, schemas: Value) -> Value {
+        let spec = normalize_schema_formats(spec_with_payload(schema_format, payload, schemas));
+        spec.pointer("/channels/test/publish/message/payload")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn parses_schema_formats() {
+        assert_eq!(SchemaFormat::parse(OPENAPI), Some(SchemaFormat::OpenApi));
+        assert_eq!(
+            SchemaFormat::parse("application/vnd.oai.openapi+yaml; version=3.0.0"),
+            Some(SchemaFormat::OpenApi)
+        );
+        assert_eq!(SchemaFormat::parse(RAML), Some(SchemaFormat::Raml));
+        assert_eq!(
+            SchemaFormat::parse("application/schema+json;version=draft-07"),
+            None
+        );
+    }
+
+    #[test]
+    fn keeps_openapi_nullable() {
+        let payload = normalized_payload(
+            OPENAPI,
+            json!({ "type": "string", "nullable": true }),
+            json!({}),
+        );
+        assert_eq!(payload, json!({ "type": "string", "nullable": true }));
+    }
+
+    #[test]
+    fn turns_openapi_type_arrays_into_nullable() {
+        let payload =
+            normalized_payload(OPENAPI, json!({ "type": ["integer", "null"] }), json!({}));
+        assert_eq!(payload, json!({ "type": "integer", "nullable": true }));
+
+        let payload =
+            normalized_payload(OPENAPI, json!({ "type": ["integer", "string"] }), json!({}));
+        assert_eq!(
+            payload,
+            json!({ "oneOf": [{ "type": "integer" }, { "type": "string" }] })
+        );
+    }
+
+    #[test]
+    fn turns_boolean_exclusive_bounds_into_numbers() {
+        let payload = normalized_payload(
+            OPENAPI,
+            json!({ "type": "integer", "minimum": 0, "exclusiveMinimum": true, "maximum": 10, "exclusiveMaximum": false }),
+            json!({}),
+        );
+        assert_eq!(
+            payload,
+            json!({ "type": "integer", "exclusiveMinimum": 0, "maximum": 10 })
+        );
+    }
+
+    #[test]
+    fn turns_discriminator_mapping_into_one_of() {
+        let payload = normalized_payload(
+            OPENAPI,
+            json!({
+                "discriminator": {
+                    "propertyName": "petType",
+                    "mapping": { "cat": "#/components/schemas/cat", "dog": "dog" }
+                }
+            }),
+            json!({
+                "cat": { "type": "object", "properties": { "lives": { "type": ["integer", "null"] } } },
+                "dog": { "type": "object" }
+            }),
+        );
+        assert_eq!(
+            payload,
+            json!({
+                "discriminator": "petType",
+                "oneOf": [
+                    { "type": "object", "properties": { "lives": { "type": "integer", "nullable": true } } },
+                    { "type": "object" }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn normalizes_raml_properties() {
+        let payload = normalized_payload(
+            RAML,
+            json!({
+                "properties": {
+                    "id": "integer",
+                    "note?": "string",
+                    "comment": { "type": "string", "required": false },
+                    "placedAt": "datetime",
+                    "quantity": { "type": "integer", "format": "int8" }
+                }
+            }),
+            json!({}),
+        );
+        assert_eq!(
+            payload,
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer" },
+                    "note": { "type": "string", "nullable": true },
+                    "comment": { "type": "string", "nullable": true },
+                    "placedAt": { "type": "string", "format": "date-time" },
+                    "quantity": { "type": "integer", "format": "int32" }
+                },
+                "required": ["id", "placedAt", "quantity"]
+            })
+        );
+    }
+
+    #[test]
+    fn normalizes_raml_type_expressions() {
+        let mut normalizer = Normalizer {
+            spec: &json!({}),
+            format: SchemaFormat::Raml,
+            resolving: vec![],
+        };
+        assert_eq!(
+            normalizer.raml_type_expression("string[]"),
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+        assert_eq!(
+            normalizer.raml_type_expression("string | nil"),
+            json!({ "type": "string", "nullable": true })
+        );
+        assert_eq!(
+            normalizer.raml_type_expression("string | integer"),
+            json!({ "oneOf": [{ "type": "string" }, { "type": "integer" }] })
+        );
+        assert_eq!(
+            normalizer.raml_type_expression("date-only"),
+            json!({ "type": "string", "format": "date" })
+        );
+        assert_eq!(
+            normalizer.raml_type_expression("Address"),
+            json!({ "$ref": "#/components/schemas/Address" })
+        );
+    }
+
+    #[test]
+    fn defaults_raml_types() {
+        assert_eq!(
+            normalized_payload(RAML, json!({ "items": "string" }), json!({})),
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+        assert_eq!(
+            normalized_payload(RAML, json!({ "pattern": "^[a-z]+$" }), json!({})),
+            json!({ "type": "string", "pattern": "^[a-z]+$" })
+        );
+    }
+
+    #[test]
+    fn inlines_raml_user_types() {
+        let schemas = json!({
+            "Item": { "properties": { "sku": "string" } },
+            "Named": { "properties": { "name": "string" } }
+        });
+        let item = json!({ "type": "object", "properties": { "sku": { "type": "string" } }, "required": ["sku"] });
+        assert_eq!(
+            normalized_payload(RAML, json!({ "type": "Item[]" }), schemas.clone()),
+            json!({ "type": "array", "items": item })
+        );
+        assert_eq!(
+            normalized_payload(RAML, json!({ "type": "Item | nil" }), schemas.clone()),
+            json!({ "type": "object", "properties": { "sku": { "type": "string" } }, "required": ["sku"], "nullable": true })
+        );
+        let payload = normalized_payload(RAML, json!({ "type": ["Item", "Named"] }), schemas);
+        assert_eq!(payload["allOf"][0], item);
+        assert_eq!(payload["allOf"][1]["required"], json!(["name"]));
+    }
+
+    #[test]
+    fn keeps_recursive_references() {
+        let payload = normalized_payload(
+            RAML,
+            json!({ "type": "Node" }),
+            json!({ "Node": { "properties": { "children?": "Node[]" } } }),
+        );
+        assert_eq!(
+            payload["properties"]["children"]["items"],
+            json!({ "$ref": "#/components/schemas/Node" })
+        );
+    }
+
+    #[test]
+    fn leaves_shared_component_schemas_untouched() {
+        let component = json!({ "type": ["string", "null"] });
+        let mut spec = spec_with_payload(
+            OPENAPI,
+            json!({ "$ref": "#/components/schemas/shared" }),
+            json!({ "shared": component }),
+        );
+        spec["channels"]["other"] = json!({
+            "publish": { "message": { "payload": { "$ref": "#/components/schemas/shared" } } }
+        });
+        let spec = normalize_schema_formats(spec);
+        assert_eq!(spec["components"]["schemas"]["shared"], component);
+        assert_eq!(
+            spec["channels"]["other"]["publish"]["message"]["payload"],
+            json!({ "$ref": "#/components/schemas/shared" })
+        );
+        assert_eq!(
+            spec["channels"]["test"]["publish"]["message"]["payload"],
+            json!({ "type": "string", "nullable": true })
+        );
+    }
+
+    #[test]
+    fn validates_nullable_with_type_arrays() {
+        let payload = json!({ "type": "object", "properties": { "note": { "type": "string", "nullable": true } } });
+        assert_eq!(
+            validation_schema(Some(&json!(RAML)), payload.clone()),
+            json!({ "type": "object", "properties": { "note": { "type": ["string", "null"] } } })
+        );
+        assert_eq!(validation_schema(None, payload.clone()), payload);
+    }
+
+    #[test]
+    #[ignore = "builds the generated service, which needs its dependencies from crates.io"]
+    fn generated_service_builds() {
+        let spec = read_json_or_yaml_to_value(Path::new("./example/specs/schema_formats.yaml"));
+        let spec: AsyncAPI = serde_json::from_value(preprocess_schema(spec)).unwrap();
+        let context = create_template_context(&spec).unwrap();
+        let output_path = Path::new("./test_output/schema_formats");
+        render_write_all_embedded_templates(&context, output_path);
+        let status = Command::new("cargo")
+            .arg("build")
+            .arg("--manifest-path")
+            .arg(output_path.join("Cargo.toml"))
+            .status()
+            .expect("cargo could not be run");
+        assert!(status.success());
+    }
+}
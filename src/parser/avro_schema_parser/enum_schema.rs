@@ -0,0 +1,51 @@
+use super::*;
+
+// parses an avro enum to a rust enum with unit variants, serialized as the original symbols
+pub fn parse_enum_schema(
+    definition: &Map<String, Value>,
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let identifyer = validate_identifier_string(avro_name(name(definition, property_name)?), true);
+
+    let symbols = match definition.get("symbols") {
+        Some(Value::Array(symbols)) => symbols,
+        _ => {
+            return Err(SchemaParserError::GenericError(
+                "Avro enum without symbols".to_string(),
+                property_name.to_string().into(),
+            ))
+        }
+    };
+
+    let mut string_builder: String = format!(
+        "#[derive(Clone, Debug, Deserialize, Serialize)]\npub enum {} {{\n",
+        identifyer
+    );
+    for symbol in symbols {
+        let symbol = match symbol.as_str() {
+            Some(symbol) => symbol,
+            None => {
+                return Err(SchemaParserError::GenericError(
+                    format!("Avro enum symbol {} is not a string", symbol),
+                    property_name.to_string().into(),
+                ))
+            }
+        };
+        // symbols are usually SCREAMING_CASE, so they are lowercased before converting them to camel case
+        let variant = validate_identifier_string(&symbol.to_lowercase(), true);
+        string_builder.push_str(&format!(
+            "#[serde(rename = \"{}\")]\n{},\n",
+            symbol, variant
+        ));
+    }
+    string_builder.push_str("}\n");
+
+    Ok(RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: property_name.to_string(),
+        struct_reference: identifyer,
+        model_definition: string_builder,
+        related_models: vec![],
+        model_type: "unit_enum".to_string(),
+    })
+}
@@ -0,0 +1,169 @@
+use serde_json::{Map, Value};
+
+use super::{
+    common::validate_identifier_string,
+    json_schema_parser::{types::RustSchemaRepresentation, SchemaParserError},
+};
+
+mod enum_schema;
+mod record_schema;
+mod union_schema;
+
+// parses an avro schema (https://avro.apache.org/docs/1.11.1/specification/) to a rust type
+pub fn parse_avro_schema_to_rust_type(
+    schema: &Value,
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    match schema {
+        Value::String(type_name) => Ok(named_type_reference(type_name, property_name)),
+        Value::Array(variants) => union_schema::parse_union_schema(variants, property_name),
+        Value::Object(definition) => match definition.get("type") {
+            Some(Value::String(type_name)) => match type_name.as_str() {
+                "record" | "error" => record_schema::parse_record_schema(definition, property_name),
+                "enum" => enum_schema::parse_enum_schema(definition, property_name),
+                "array" => parse_collection_schema(definition, "items", "Vec<{}>", property_name),
+                "map" => parse_collection_schema(
+                    definition,
+                    "values",
+                    "std::collections::HashMap<String, {}>",
+                    property_name,
+                ),
+                "fixed" => parse_fixed_schema(definition, property_name),
+                // primitive types, logical types are represented by their underlying type
+                _ => Ok(named_type_reference(type_name, property_name)),
+            },
+            // e.g. {"type": {"type": "array", "items": "string"}}
+            Some(nested_schema) => parse_avro_schema_to_rust_type(nested_schema, property_name),
+            None => Err(SchemaParserError::GenericError(
+                "Avro schema without type".to_string(),
+                property_name.to_string().into(),
+            )),
+        },
+        _ => Err(SchemaParserError::GenericError(
+            format!("Invalid avro schema {}", schema),
+            property_name.to_string().into(),
+        )),
+    }
+}
+
+// maps primitive types to rust types, any other name references a named type (record, enum or fixed) defined elsewhere
+fn named_type_reference(type_name: &str, property_name: &str) -> RustSchemaRepresentation {
+    let struct_reference = match type_name {
+        "null" => "()".to_string(),
+        "boolean" => "bool".to_string(),
+        "int" => "i32".to_string(),
+        "long" => "i64".to_string(),
+        "float" => "f32".to_string(),
+        "double" => "f64".to_string(),
+        "bytes" => "Vec<u8>".to_string(),
+        "string" => "String".to_string(),
+        // named types are referenced by their name without namespace
+        full_name => validate_identifier_string(avro_name(full_name), true),
+    };
+    RustSchemaRepresentation {
+        unique_id: validate_identifier_string(property_name, false),
+        original_key: property_name.to_string(),
+        struct_reference,
+        model_definition: "".to_string(),
+        related_models: vec![],
+        model_type: "primitive".to_string(),
+    }
+}
+
+// parses arrays and maps, `reference_template` wraps the item type (e.g. `Vec<{}>`)
+fn parse_collection_schema(
+    definition: &Map<String, Value>,
+    items_key: &str,
+    reference_template: &str,
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let items = match definition.get(items_key) {
+        Some(items) => parse_avro_schema_to_rust_type(items, property_name)?,
+        None => {
+            return Err(SchemaParserError::GenericError(
+                format!("Avro schema without {}", items_key),
+                property_name.to_string().into(),
+            ))
+        }
+    };
+    Ok(RustSchemaRepresentation {
+        unique_id: validate_identifier_string(property_name, false),
+        original_key: property_name.to_string(),
+        struct_reference: reference_template.replace("{}", &items.struct_reference),
+        model_definition: "".to_string(),
+        related_models: vec![items],
+        model_type: "array".to_string(),
+    })
+}
+
+// fixed is a named type, so it gets an alias which can be referenced by name
+fn parse_fixed_schema(
+    definition: &Map<String, Value>,
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let identifyer = validate_identifier_string(avro_name(name(definition, property_name)?), true);
+    Ok(RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: property_name.to_string(),
+        struct_reference: identifyer.clone(),
+        model_definition: format!("pub type {} = Vec<u8>;\n", identifyer),
+        related_models: vec![],
+        model_type: "primitive".to_string(),
+    })
+}
+
+// the name of a named type, which is required by the avro specification
+fn name<'a>(
+    definition: &'a Map<String, Value>,
+    property_name: &str,
+) -> Result<&'a str, SchemaParserError> {
+    match definition.get("name") {
+        Some(Value::String(name)) => Ok(name),
+        _ => Err(SchemaParserError::GenericError(
+            "Avro named type without name".to_string(),
+            property_name.to_string().into(),
+        )),
+    }
+}
+
+// strips the namespace of a full name (e.g. com.example.User -> User)
+fn avro_name(full_name: &str) -> &str {
+    full_name.rsplit('.').next().unwrap_or(full_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::*;
+
+    use super::parse_avro_schema_to_rust_type;
+
+    const SCHEMAS: [&str; 1] = ["./example/schemas/userSignedUp.avsc"];
+
+    #[test]
+    fn can_parse_avro_schema() {
+        for schema_path in SCHEMAS {
+            let string_content = fs::read_to_string(schema_path).expect("file could not be read");
+            let schema = serde_json::from_str::<serde_json::Value>(&string_content).unwrap();
+            let parsed = parse_avro_schema_to_rust_type(&schema, "payload").unwrap();
+            let filename_without_extension = Path::new(schema_path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap();
+            let out_dir = Path::new("./test_output/avro").join(filename_without_extension);
+            utils::write_to_path_create_dir(
+                parsed
+                    .get_related_models_recursive()
+                    .iter()
+                    .map(|x| x.model_definition.clone())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+                    .as_str(),
+                &out_dir,
+            )
+            .unwrap();
+        }
+    }
+}
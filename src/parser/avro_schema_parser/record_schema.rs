@@ -0,0 +1,67 @@
+use super::*;
+
+// parses an avro record to a rust struct, its fields are parsed recursively
+pub fn parse_record_schema(
+    definition: &Map<String, Value>,
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let identifyer = validate_identifier_string(avro_name(name(definition, property_name)?), true);
+
+    let fields = match definition.get("fields") {
+        Some(Value::Array(fields)) => fields,
+        _ => {
+            return Err(SchemaParserError::GenericError(
+                "Avro record without fields".to_string(),
+                property_name.to_string().into(),
+            ))
+        }
+    };
+
+    let mut field_types: Vec<RustSchemaRepresentation> = vec![];
+    let mut field_strings: Vec<String> = vec![];
+    for field in fields {
+        let field_name = match field.get("name") {
+            Some(Value::String(field_name)) => field_name,
+            _ => {
+                return Err(SchemaParserError::GenericError(
+                    "Avro record field without name".to_string(),
+                    property_name.to_string().into(),
+                ))
+            }
+        };
+        let field_type = match field.get("type") {
+            Some(field_type) => parse_avro_schema_to_rust_type(field_type, field_name)?,
+            None => {
+                return Err(SchemaParserError::GenericError(
+                    "Avro record field without type".to_string(),
+                    field_name.to_string().into(),
+                ))
+            }
+        };
+        let identifier = validate_identifier_string(field_name, false);
+        let rename = match field_name == &identifier {
+            true => "".to_string(),
+            false => format!("#[serde(rename = \"{}\")]\n", field_name),
+        };
+        field_strings.push(format!(
+            "{}pub {}: {}",
+            rename, identifier, field_type.struct_reference
+        ));
+        field_types.push(field_type);
+    }
+
+    let full_struct = format!(
+        "#[derive(Clone, Debug, Deserialize, Serialize)]\npub struct {} {{\n{}\n}}\n",
+        identifyer,
+        field_strings.join(",\n")
+    );
+
+    Ok(RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: property_name.to_string(),
+        struct_reference: identifyer,
+        model_definition: full_struct,
+        related_models: field_types,
+        model_type: "struct".to_string(),
+    })
+}
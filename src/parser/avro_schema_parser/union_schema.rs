@@ -0,0 +1,80 @@
+use super::*;
+
+// parses an avro union, a union with "null" becomes an Option, other unions become an untagged enum
+pub fn parse_union_schema(
+    variants: &[Value],
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let non_null_variants: Vec<&Value> = variants
+        .iter()
+        .filter(|variant| variant.as_str() != Some("null"))
+        .collect();
+
+    let inner = match non_null_variants.as_slice() {
+        [] => {
+            return Err(SchemaParserError::GenericError(
+                "Avro union without non null types".to_string(),
+                property_name.to_string().into(),
+            ))
+        }
+        [single_variant] => parse_avro_schema_to_rust_type(single_variant, property_name)?,
+        multiple_variants => parse_variants(multiple_variants, property_name)?,
+    };
+
+    if non_null_variants.len() == variants.len() {
+        return Ok(inner);
+    }
+    Ok(RustSchemaRepresentation {
+        unique_id: validate_identifier_string(property_name, false),
+        original_key: property_name.to_string(),
+        struct_reference: format!("Option<{}>", inner.struct_reference),
+        model_definition: "".to_string(),
+        related_models: vec![inner],
+        model_type: "option".to_string(),
+    })
+}
+
+// assembles an untagged enum, the variants are named after their avro type
+fn parse_variants(
+    variants: &[&Value],
+    property_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let identifyer = validate_identifier_string(format!("{}Enum", property_name).as_str(), true);
+
+    let mut variant_representations: Vec<RustSchemaRepresentation> = vec![];
+    for (index, variant) in variants.iter().enumerate() {
+        let variant_name = match variant {
+            Value::String(type_name) => avro_name(type_name).to_string(),
+            Value::Object(definition) => match (definition.get("name"), definition.get("type")) {
+                (Some(Value::String(name)), _) => avro_name(name).to_string(),
+                (_, Some(Value::String(type_name))) => type_name.to_string(),
+                _ => format!("{}Variant{}", property_name, index + 1),
+            },
+            _ => format!("{}Variant{}", property_name, index + 1),
+        };
+        let variant_name = validate_identifier_string(&variant_name, true);
+        let mut representation = parse_avro_schema_to_rust_type(variant, &variant_name)?;
+        // the enum variants are named after the unique id of the related models, like for json schema enums
+        representation.unique_id = variant_name;
+        variant_representations.push(representation);
+    }
+
+    let mut string_builder: String = format!(
+        "#[derive(Clone, Debug, Deserialize, Serialize)]\n#[serde(untagged)]\npub enum {} {{\n",
+        identifyer
+    );
+    variant_representations.iter().for_each(|variant| {
+        let content = format!("{}({}),\n", variant.unique_id, variant.struct_reference);
+        string_builder.push_str(&content);
+    });
+    string_builder.push_str("}\n");
+
+    Ok(RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: property_name.to_string(),
+        struct_reference: identifyer,
+        model_definition: string_builder,
+        related_models: variant_representations,
+        model_type: "enum".to_string(),
+    })
+}
@@ -36,6 +36,15 @@ pub fn read_json_or_yaml_to_value(file_path: &Path) -> serde_json::Value {
     }
 }
 
+// identifiers rust reserves, snake case identifiers equal to one of them get a trailing `_`
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
 pub fn validate_identifier_string(s: &str, camel_case: bool) -> String {
     let re = Regex::new(r"[^a-zA-Z0-9\s]").unwrap();
     let mut sanitized = String::new();
@@ -72,6 +81,9 @@ pub fn validate_identifier_string(s: &str, camel_case: bool) -> String {
             .collect();
     } else {
         sanitized = words.join("_").to_lowercase();
+        if RUST_KEYWORDS.contains(&sanitized.as_str()) {
+            sanitized.push('_');
+        }
     }
     sanitized
 }
@@ -11,8 +11,29 @@ pub fn parse_array_schema(
     let item_type = match &array_type.items {
         Some(type_box) => match type_box {
             ReferenceOr::Item(schema) => match &schema.schema_kind {
+                SchemaKind::Type(Type::Object(_)) | SchemaKind::Type(Type::Array(_)) => {
+                    // named item schemas keep their name, e.g. the items of `OrderItem[]`
+                    let item_name = match &schema.schema_data.name {
+                        Some(name) => name.clone(),
+                        None => format!("{}Item", property_name),
+                    };
+                    let item = parse_json_schema_to_rust_type(schema, &item_name)?;
+                    return Ok(RustSchemaRepresentation {
+                        unique_id: identifyer,
+                        original_key: property_name.to_string(),
+                        struct_reference: format!("Vec<{}>", item.struct_reference),
+                        model_definition: "".to_string(),
+                        related_models: vec![item],
+                        model_type: "array".to_string(),
+                    });
+                }
                 SchemaKind::Type(schema_type) => format_to_rust_type(schema_type),
-                _ => panic!("Unsupported schema kind"),
+                _ => {
+                    return Err(SchemaParserError::GenericError(
+                        "Array items without a type are not supported yet".to_string(),
+                        Some(property_name.to_string()),
+                    ))
+                }
             },
             ReferenceOr::Reference { reference: _ } => {
                 return Err(SchemaParserError::GenericError(
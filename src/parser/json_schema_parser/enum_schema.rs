@@ -11,18 +11,18 @@ pub fn parse_enum_schema(
         .map(|(index, schema)| match schema {
             ReferenceOr::Item(item_schema) => {
                 let payload_variant_name = format!("{}Variant{}", property_name, index + 1);
-                parse_json_schema_to_rust_type(item_schema, payload_variant_name.as_str()).unwrap()
+                parse_json_schema_to_rust_type(item_schema, payload_variant_name.as_str())
             }
             ReferenceOr::Reference { reference: _ } => {
                 panic!("Refs should be resolved by now");
             }
         })
-        .collect();
+        .collect::<Result<_, _>>()?;
 
     let identifyer = validate_identifier_string(format!("{}Enum", property_name).as_str(), true);
     // assemble the enum
     let mut string_builder: String = format!(
-        "#[derive(Serialize, Deserialize, Debug)]\n#[serde(untagged)]\npub enum {} {{\n",
+        "#[derive(Clone, Debug, Deserialize, Serialize)]\n#[serde(untagged)]\npub enum {} {{\n",
         identifyer
     );
 
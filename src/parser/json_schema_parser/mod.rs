@@ -20,7 +20,7 @@ pub fn parse_json_schema_to_rust_type(
     property_name: &str,
 ) -> Result<RustSchemaRepresentation, types::SchemaParserError> {
     let schema_kind: &SchemaKind = &schema.schema_kind;
-    match schema_kind {
+    let representation = match schema_kind {
         SchemaKind::Type(schema_type) => match schema_type {
             Type::Object(y) => object_schema::parse_object_schema(y, property_name),
             Type::Array(array_type) => array_schema::parse_array_schema(array_type, property_name),
@@ -31,9 +31,18 @@ pub fn parse_json_schema_to_rust_type(
         SchemaKind::OneOf { one_of }
         | SchemaKind::AnyOf { any_of: one_of }
         | SchemaKind::AllOf { all_of: one_of } => parse_enum_schema(one_of, property_name),
-        SchemaKind::Any(_s) => {
-            panic!("Unsupported schema kind {:?}", _s);
-        }
+        SchemaKind::Any(_) => Err(types::SchemaParserError::GenericError(
+            "Schemas without a type are not supported yet".to_string(),
+            Some(property_name.to_string()),
+        )),
+    };
+    // nullable schemas (e.g. from OpenAPI or optional RAML properties) become Options
+    match (representation, schema.schema_data.nullable) {
+        (Ok(representation), true) => Ok(RustSchemaRepresentation {
+            struct_reference: format!("Option<{}>", representation.struct_reference),
+            ..representation
+        }),
+        (representation, _) => representation,
     }
 }
 
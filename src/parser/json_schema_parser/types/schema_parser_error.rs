@@ -12,7 +12,7 @@ impl fmt::Display for SchemaParserError {
                 let base_message = match property_name {
                     Some(name) => format!(
                         "Error while parsing schema, inside property:\"{}\";\n Message: {} ",
-                        msg, name
+                        name, msg
                     ),
                     None => "Error while parsing schema".to_string() + &msg.to_string(),
                 };
@@ -1,3 +1,5 @@
 pub mod asyncapi_model_parser;
+pub mod avro_schema_parser;
 pub mod common;
 pub mod json_schema_parser;
+pub mod protobuf_schema_parser;
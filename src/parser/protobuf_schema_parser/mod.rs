@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+
+use super::{
+    common::validate_identifier_string,
+    json_schema_parser::{types::RustSchemaRepresentation, SchemaParserError},
+};
+
+mod proto_file;
+use proto_file::{parse_proto_file, ProtoEnum, ProtoField, ProtoLabel, ProtoMessage};
+
+// the kind of a named proto type, fields of enum types are represented as i32 by prost
+#[derive(Clone, Copy, PartialEq)]
+enum TypeKind {
+    Message,
+    Enum,
+}
+
+// parses a .proto definition to prost compatible rust types
+// the message named `message_name` is the payload, if there is none the first message is used
+pub fn parse_protobuf_schema_to_rust_type(
+    source: &str,
+    message_name: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
+    let file = parse_proto_file(source)?;
+
+    // nested types are flattened, so Outer.Inner becomes OuterInner
+    let mut types: HashMap<String, (String, TypeKind)> = HashMap::new();
+    collect_types(&file.messages, &file.enums, "", &mut types);
+
+    let mut models: Vec<RustSchemaRepresentation> = vec![];
+    for message in &file.messages {
+        parse_message(message, "", file.package.as_deref(), &types, &mut models)?;
+    }
+    for proto_enum in &file.enums {
+        models.push(parse_enum(proto_enum, ""));
+    }
+
+    let payload_name = validate_identifier_string(message_name, true);
+    let payload_name = file
+        .messages
+        .iter()
+        .map(|message| validate_identifier_string(&message.name, true))
+        .find(|name| *name == payload_name)
+        .or_else(|| {
+            file.messages
+                .first()
+                .map(|message| validate_identifier_string(&message.name, true))
+        });
+    match payload_name.and_then(|name| models.iter().position(|model| model.unique_id == name)) {
+        Some(index) => {
+            let mut payload = models.remove(index);
+            payload.related_models = models;
+            Ok(payload)
+        }
+        None => Err(SchemaParserError::GenericError(
+            "Proto definition without message".to_string(),
+            message_name.to_string().into(),
+        )),
+    }
+}
+
+fn collect_types(
+    messages: &[ProtoMessage],
+    enums: &[ProtoEnum],
+    scope: &str,
+    types: &mut HashMap<String, (String, TypeKind)>,
+) {
+    for message in messages {
+        let full_name = format!("{}{}", scope, message.name);
+        types.insert(
+            full_name.clone(),
+            (rust_type_name(&full_name), TypeKind::Message),
+        );
+        collect_types(
+            &message.messages,
+            &message.enums,
+            &format!("{}.", full_name),
+            types,
+        );
+    }
+    for proto_enum in enums {
+        let full_name = format!("{}{}", scope, proto_enum.name);
+        types.insert(
+            full_name.clone(),
+            (rust_type_name(&full_name), TypeKind::Enum),
+        );
+    }
+}
+
+fn rust_type_name(full_name: &str) -> String {
+    full_name
+        .split('.')
+        .map(|name| validate_identifier_string(name, true))
+        .collect()
+}
+
+// resolves a type reference like the protobuf compiler, starting at the innermost scope
+fn resolve_type<'a>(
+    type_name: &str,
+    scope: &str,
+    package: Option<&str>,
+    types: &'a HashMap<String, (String, TypeKind)>,
+) -> Result<&'a (String, TypeKind), SchemaParserError> {
+    let mut type_name = type_name.trim_start_matches('.');
+    if let Some(package) = package {
+        type_name = type_name
+            .strip_prefix(&format!("{}.", package))
+            .unwrap_or(type_name);
+    }
+    let mut scope_parts: Vec<&str> = scope.split('.').filter(|s| !s.is_empty()).collect();
+    loop {
+        let candidate = match scope_parts.is_empty() {
+            true => type_name.to_string(),
+            false => format!("{}.{}", scope_parts.join("."), type_name),
+        };
+        if let Some(resolved) = types.get(&candidate) {
+            return Ok(resolved);
+        }
+        if scope_parts.pop().is_none() {
+            return Err(SchemaParserError::GenericError(
+                format!("Unknown proto type {}", type_name),
+                scope.to_string().into(),
+            ));
+        }
+    }
+}
+
+// maps scalar proto types to rust types, None for message and enum types
+fn scalar_rust_type(type_name: &str) -> Option<&'static str> {
+    match type_name {
+        "double" => Some("f64"),
+        "float" => Some("f32"),
+        "int32" | "sint32" | "sfixed32" => Some("i32"),
+        "int64" | "sint64" | "sfixed64" => Some("i64"),
+        "uint32" | "fixed32" => Some("u32"),
+        "uint64" | "fixed64" => Some("u64"),
+        "bool" => Some("bool"),
+        "string" => Some("String"),
+        "bytes" => Some("Vec<u8>"),
+        _ => None,
+    }
+}
+
+// the prost attribute and rust type of a field type, e.g. ("int64", "i64") or ("message", "User")
+// the flag is set for message types, which prost always wraps in an Option
+fn field_type(
+    type_name: &str,
+    scope: &str,
+    package: Option<&str>,
+    types: &HashMap<String, (String, TypeKind)>,
+) -> Result<(String, String, bool), SchemaParserError> {
+    match scalar_rust_type(type_name) {
+        Some("Vec<u8>") => Ok(("bytes = \"vec\"".to_string(), "Vec<u8>".to_string(), false)),
+        Some(rust_type) => Ok((type_name.to_string(), rust_type.to_string(), false)),
+        None => match resolve_type(type_name, scope, package, types)? {
+            (rust_type, TypeKind::Message) => Ok(("message".to_string(), rust_type.clone(), true)),
+            (rust_type, TypeKind::Enum) => Ok((
+                format!("enumeration = \"{}\"", rust_type),
+                "i32".to_string(),
+                false,
+            )),
+        },
+    }
+}
+
+// the prost map attribute of a map value, e.g. `string, int64` or `string, enumeration(Plan)`
+fn map_value_type(
+    type_name: &str,
+    scope: &str,
+    package: Option<&str>,
+    types: &HashMap<String, (String, TypeKind)>,
+) -> Result<(String, String), SchemaParserError> {
+    match scalar_rust_type(type_name) {
+        Some(rust_type) => Ok((type_name.to_string(), rust_type.to_string())),
+        None => match resolve_type(type_name, scope, package, types)? {
+            (rust_type, TypeKind::Message) => Ok(("message".to_string(), rust_type.clone())),
+            (rust_type, TypeKind::Enum) => {
+                Ok((format!("enumeration({})", rust_type), "i32".to_string()))
+            }
+        },
+    }
+}
+
+fn parse_field(
+    field: &ProtoField,
+    message_type: &str,
+    scope: &str,
+    package: Option<&str>,
+    types: &HashMap<String, (String, TypeKind)>,
+) -> Result<String, SchemaParserError> {
+    let identifier = validate_identifier_string(&field.name, false);
+    let rename = match field.name == identifier {
+        true => "".to_string(),
+        false => format!("#[serde(rename = \"{}\")]\n", field.name),
+    };
+    let (attribute, rust_type) = match &field.map {
+        Some((key_type, value_type)) => {
+            let key_rust_type = scalar_rust_type(key_type).ok_or_else(|| {
+                SchemaParserError::GenericError(
+                    format!("Invalid map key type {}", key_type),
+                    field.name.clone().into(),
+                )
+            })?;
+            let (value_attribute, value_rust_type) =
+                map_value_type(value_type, scope, package, types)?;
+            (
+                format!("map = \"{}, {}\"", key_type, value_attribute),
+                format!(
+                    "std::collections::HashMap<{}, {}>",
+                    key_rust_type, value_rust_type
+                ),
+            )
+        }
+        None => {
+            let (attribute, rust_type, is_message) =
+                field_type(&field.type_name, scope, package, types)?;
+            match (&field.label, is_message) {
+                (ProtoLabel::Repeated, _) => (
+                    format!("{}, repeated", attribute),
+                    format!("Vec<{}>", rust_type),
+                ),
+                // a message containing itself has to be boxed
+                (_, true) if rust_type == message_type => (
+                    format!("{}, optional, boxed", attribute),
+                    format!("Option<Box<{}>>", rust_type),
+                ),
+                (_, true) => (
+                    format!("{}, optional", attribute),
+                    format!("Option<{}>", rust_type),
+                ),
+                (ProtoLabel::Optional, _) => (
+                    format!("{}, optional", attribute),
+                    format!("Option<{}>", rust_type),
+                ),
+                (ProtoLabel::Singular, _) => (attribute, rust_type),
+            }
+        }
+    };
+    Ok(format!(
+        "#[prost({}, tag = \"{}\")]\n{}pub {}: {}",
+        attribute, field.tag, rename, identifier, rust_type
+    ))
+}
+
+// parses a message and its nested types, all models are appended to `models`
+fn parse_message(
+    message: &ProtoMessage,
+    scope: &str,
+    package: Option<&str>,
+    types: &HashMap<String, (String, TypeKind)>,
+    models: &mut Vec<RustSchemaRepresentation>,
+) -> Result<(), SchemaParserError> {
+    let full_name = format!("{}{}", scope, message.name);
+    let identifyer = rust_type_name(&full_name);
+    let message_scope = format!("{}.", full_name);
+
+    for nested_message in &message.messages {
+        parse_message(nested_message, &message_scope, package, types, models)?;
+    }
+    for nested_enum in &message.enums {
+        models.push(parse_enum(nested_enum, &message_scope));
+    }
+
+    let mut property_strings: Vec<String> = vec![];
+    for field in &message.fields {
+        property_strings.push(parse_field(
+            field,
+            &identifyer,
+            &message_scope,
+            package,
+            types,
+        )?);
+    }
+
+    for oneof in &message.oneofs {
+        let oneof_identifyer = format!(
+            "{}{}",
+            identifyer,
+            validate_identifier_string(&oneof.name, true)
+        );
+        let mut variant_strings: Vec<String> = vec![];
+        for field in &oneof.fields {
+            let (attribute, rust_type, _) =
+                field_type(&field.type_name, &message_scope, package, types)?;
+            variant_strings.push(format!(
+                "#[prost({}, tag = \"{}\")]\n{}({})",
+                attribute,
+                field.tag,
+                validate_identifier_string(&field.name, true),
+                rust_type
+            ));
+        }
+        models.push(RustSchemaRepresentation {
+            unique_id: oneof_identifyer.clone(),
+            original_key: oneof.name.clone(),
+            struct_reference: oneof_identifyer.clone(),
+            model_definition: format!(
+                "#[derive(Clone, PartialEq, ::prost::Oneof, Serialize, Deserialize)]\npub enum {} {{\n{},\n}}\n",
+                oneof_identifyer,
+                variant_strings.join(",\n")
+            ),
+            related_models: vec![],
+            model_type: "enum".to_string(),
+        });
+        let tags = oneof
+            .fields
+            .iter()
+            .map(|field| field.tag.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        property_strings.push(format!(
+            "#[prost(oneof = \"{}\", tags = \"{}\")]\npub {}: Option<{}>",
+            oneof_identifyer,
+            tags,
+            validate_identifier_string(&oneof.name, false),
+            oneof_identifyer
+        ));
+    }
+
+    // prost implements Debug for messages
+    let full_struct = format!(
+        "#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]\npub struct {} {{\n{}\n}}\n",
+        identifyer,
+        property_strings.join(",\n")
+    );
+    models.push(RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: message.name.clone(),
+        struct_reference: identifyer,
+        model_definition: full_struct,
+        related_models: vec![],
+        model_type: "struct".to_string(),
+    });
+    Ok(())
+}
+
+fn parse_enum(proto_enum: &ProtoEnum, scope: &str) -> RustSchemaRepresentation {
+    let identifyer = rust_type_name(&format!("{}{}", scope, proto_enum.name));
+    // like prost, the common prefix of the values (e.g. PLAN_ in PLAN_FREE) is stripped
+    let prefix = format!(
+        "{}_",
+        validate_identifier_string(&proto_enum.name, false).to_uppercase()
+    );
+    let variants = proto_enum
+        .values
+        .iter()
+        .map(|(name, value)| {
+            let stripped = name.strip_prefix(&prefix).unwrap_or(name);
+            format!(
+                "{} = {}",
+                validate_identifier_string(&stripped.to_lowercase(), true),
+                value
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+    RustSchemaRepresentation {
+        unique_id: identifyer.clone(),
+        original_key: proto_enum.name.clone(),
+        struct_reference: identifyer.clone(),
+        model_definition: format!(
+            "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration, Serialize, Deserialize)]\n#[repr(i32)]\npub enum {} {{\n{},\n}}\n",
+            identifyer, variants
+        ),
+        related_models: vec![],
+        model_type: "unit_enum".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::*;
+
+    use super::parse_protobuf_schema_to_rust_type;
+
+    const SCHEMAS: [&str; 1] = ["./example/schemas/userCreated.proto"];
+
+    #[test]
+    fn can_parse_protobuf_schema() {
+        for schema_path in SCHEMAS {
+            let source = fs::read_to_string(schema_path).expect("file could not be read");
+            let parsed = parse_protobuf_schema_to_rust_type(&source, "userCreated").unwrap();
+            assert_eq!(parsed.struct_reference, "UserCreated");
+            let filename_without_extension = Path::new(schema_path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap();
+            let out_dir = Path::new("./test_output/protobuf").join(filename_without_extension);
+            utils::write_to_path_create_dir(
+                parsed
+                    .get_related_models_recursive()
+                    .iter()
+                    .map(|x| x.model_definition.clone())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+                    .as_str(),
+                &out_dir,
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::parser::json_schema_parser::SchemaParserError;
+
+// the parts of a .proto file needed to generate rust types, services and options are skipped
+#[derive(Debug, Default)]
+pub struct ProtoFile {
+    pub package: Option<String>,
+    pub messages: Vec<ProtoMessage>,
+    pub enums: Vec<ProtoEnum>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProtoMessage {
+    pub name: String,
+    pub fields: Vec<ProtoField>,
+    pub oneofs: Vec<ProtoOneof>,
+    pub messages: Vec<ProtoMessage>,
+    pub enums: Vec<ProtoEnum>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProtoLabel {
+    Singular,
+    Optional,
+    Repeated,
+}
+
+#[derive(Debug)]
+pub struct ProtoField {
+    pub name: String,
+    pub label: ProtoLabel,
+    pub type_name: String,
+    // key and value type of map fields
+    pub map: Option<(String, String)>,
+    pub tag: u32,
+}
+
+#[derive(Debug)]
+pub struct ProtoOneof {
+    pub name: String,
+    pub fields: Vec<ProtoField>,
+}
+
+#[derive(Debug)]
+pub struct ProtoEnum {
+    pub name: String,
+    pub values: Vec<(String, i32)>,
+}
+
+pub fn parse_proto_file(source: &str) -> Result<ProtoFile, SchemaParserError> {
+    let mut parser = Parser {
+        tokens: tokenize(source),
+        position: 0,
+    };
+    let mut file = ProtoFile::default();
+    while let Some(token) = parser.next() {
+        match token.as_str() {
+            "syntax" | "edition" | "import" | "option" => parser.skip_statement(),
+            "package" => {
+                file.package = Some(parser.next_token()?);
+                parser.expect(";")?;
+            }
+            "message" => file.messages.push(parser.parse_message()?),
+            "enum" => file.enums.push(parser.parse_enum()?),
+            "service" | "extend" => parser.skip_block()?,
+            ";" => (),
+            unexpected => return Err(parser.error(unexpected)),
+        }
+    }
+    Ok(file)
+}
+
+// splits the source into identifiers, numbers, strings and symbols, comments are dropped
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '/' if chars.peek() == Some(&'/') => {
+                for comment_char in chars.by_ref() {
+                    if comment_char == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for comment_char in chars.by_ref() {
+                    if previous == '*' && comment_char == '/' {
+                        break;
+                    }
+                    previous = comment_char;
+                }
+            }
+            '"' | '\'' => {
+                let mut string = current_char.to_string();
+                for string_char in chars.by_ref() {
+                    string.push(string_char);
+                    if string_char == current_char {
+                        break;
+                    }
+                }
+                tokens.push(string);
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut word = c.to_string();
+                while let Some(&next_char) = chars.peek() {
+                    if next_char.is_alphanumeric() || next_char == '_' || next_char == '.' {
+                        word.push(next_char);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(word);
+            }
+            c if c.is_whitespace() => (),
+            symbol => tokens.push(symbol.to_string()),
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next_token(&mut self) -> Result<String, SchemaParserError> {
+        self.next().ok_or_else(|| {
+            SchemaParserError::GenericError("Unexpected end of proto file".to_string(), None)
+        })
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), SchemaParserError> {
+        let token = self.next_token()?;
+        match token == expected {
+            true => Ok(()),
+            false => Err(self.error(&format!("{}, expected {}", token, expected))),
+        }
+    }
+
+    fn error(&self, token: &str) -> SchemaParserError {
+        SchemaParserError::GenericError(format!("Unexpected token {} in proto file", token), None)
+    }
+
+    // skips everything up to and including the next `;`
+    fn skip_statement(&mut self) {
+        while let Some(token) = self.next() {
+            if token == ";" {
+                break;
+            }
+        }
+    }
+
+    // skips a named block like `service Name { ... }` including nested blocks
+    fn skip_block(&mut self) -> Result<(), SchemaParserError> {
+        while self.next_token()? != "{" {}
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next_token()?.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    // skips field options like `[deprecated = true]`
+    fn skip_options(&mut self) -> Result<(), SchemaParserError> {
+        if self.peek() == Some("[") {
+            while self.next_token()? != "]" {}
+        }
+        Ok(())
+    }
+
+    fn parse_tag<T: std::str::FromStr>(&mut self) -> Result<T, SchemaParserError> {
+        self.expect("=")?;
+        let tag = self.next_token()?;
+        let parsed = tag.parse::<T>().map_err(|_| self.error(&tag))?;
+        self.skip_options()?;
+        self.expect(";")?;
+        Ok(parsed)
+    }
+
+    fn parse_message(&mut self) -> Result<ProtoMessage, SchemaParserError> {
+        let mut message = ProtoMessage {
+            name: self.next_token()?,
+            ..Default::default()
+        };
+        self.expect("{")?;
+        loop {
+            let token = self.next_token()?;
+            match token.as_str() {
+                "}" => break,
+                ";" => (),
+                "message" => message.messages.push(self.parse_message()?),
+                "enum" => message.enums.push(self.parse_enum()?),
+                "oneof" => message.oneofs.push(self.parse_oneof()?),
+                "option" | "reserved" | "extensions" => self.skip_statement(),
+                "extend" => self.skip_block()?,
+                "map" => message.fields.push(self.parse_map_field()?),
+                "repeated" => {
+                    let type_name = self.next_token()?;
+                    message
+                        .fields
+                        .push(self.parse_field(ProtoLabel::Repeated, type_name)?);
+                }
+                "optional" => {
+                    let type_name = self.next_token()?;
+                    message
+                        .fields
+                        .push(self.parse_field(ProtoLabel::Optional, type_name)?);
+                }
+                // proto2 required fields are always present, like singular proto3 fields
+                "required" => {
+                    let type_name = self.next_token()?;
+                    message
+                        .fields
+                        .push(self.parse_field(ProtoLabel::Singular, type_name)?);
+                }
+                _ => message
+                    .fields
+                    .push(self.parse_field(ProtoLabel::Singular, token)?),
+            }
+        }
+        Ok(message)
+    }
+
+    fn parse_field(
+        &mut self,
+        label: ProtoLabel,
+        type_name: String,
+    ) -> Result<ProtoField, SchemaParserError> {
+        let name = self.next_token()?;
+        let tag = self.parse_tag()?;
+        Ok(ProtoField {
+            name,
+            label,
+            type_name,
+            map: None,
+            tag,
+        })
+    }
+
+    // map<key, value> name = tag;
+    fn parse_map_field(&mut self) -> Result<ProtoField, SchemaParserError> {
+        self.expect("<")?;
+        let key_type = self.next_token()?;
+        self.expect(",")?;
+        let value_type = self.next_token()?;
+        self.expect(">")?;
+        let mut field = self.parse_field(ProtoLabel::Singular, "map".to_string())?;
+        field.map = Some((key_type, value_type));
+        Ok(field)
+    }
+
+    fn parse_oneof(&mut self) -> Result<ProtoOneof, SchemaParserError> {
+        let mut oneof = ProtoOneof {
+            name: self.next_token()?,
+            fields: vec![],
+        };
+        self.expect("{")?;
+        loop {
+            let token = self.next_token()?;
+            match token.as_str() {
+                "}" => break,
+                ";" => (),
+                "option" => self.skip_statement(),
+                _ => oneof
+                    .fields
+                    .push(self.parse_field(ProtoLabel::Singular, token)?),
+            }
+        }
+        Ok(oneof)
+    }
+
+    fn parse_enum(&mut self) -> Result<ProtoEnum, SchemaParserError> {
+        let mut proto_enum = ProtoEnum {
+            name: self.next_token()?,
+            values: vec![],
+        };
+        self.expect("{")?;
+        loop {
+            let token = self.next_token()?;
+            match token.as_str() {
+                "}" => break,
+                ";" => (),
+                "option" | "reserved" => self.skip_statement(),
+                _ => {
+                    let value = self.parse_tag()?;
+                    proto_enum.values.push((token, value));
+                }
+            }
+        }
+        Ok(proto_enum)
+    }
+}
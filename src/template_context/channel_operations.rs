@@ -1,33 +1,63 @@
 use crate::asyncapi_model::AsyncAPI;
+use std::io;
 
 use super::{utilities, SimplifiedOperation};
 
-pub fn get_subscribe_channels_operations(
-    asyncapi: &AsyncAPI,
-) -> Vec<(&String, SimplifiedOperation)> {
+pub fn get_subscribe_channels_operations<'a>(
+    asyncapi: &'a AsyncAPI,
+    protocol: &str,
+) -> Result<Vec<(&'a String, SimplifiedOperation)>, io::Error> {
     asyncapi
         .channels
         .iter()
         .filter_map(|(channel_name, channel)| {
             channel.subscribe.as_ref().map(|operation| {
-                (
+                let mut simplified_operation = utilities::simplify_operation(
+                    operation,
                     channel_name,
-                    utilities::simplify_operation(operation, channel_name),
-                )
+                    channel,
+                    asyncapi.default_content_type.as_deref(),
+                    protocol,
+                )?;
+                // both operations of a channel without operationId would end up in the same module
+                if channel.publish.is_some() && operation.operation_id.is_none() {
+                    simplified_operation.unique_id += "_subscribe";
+                }
+                // we send the request, so the reply is received on the publish operation
+                simplified_operation.reply =
+                    utilities::simplify_reply(asyncapi, operation, protocol, |reply_channel| {
+                        reply_channel.publish.as_ref()
+                    })?;
+                Ok((channel_name, simplified_operation))
             })
         })
         .collect()
 }
-pub fn get_publish_channels_operations(asyncapi: &AsyncAPI) -> Vec<(&String, SimplifiedOperation)> {
+pub fn get_publish_channels_operations<'a>(
+    asyncapi: &'a AsyncAPI,
+    protocol: &str,
+) -> Result<Vec<(&'a String, SimplifiedOperation)>, io::Error> {
     asyncapi
         .channels
         .iter()
         .filter_map(|(channel_name, channel)| {
             channel.publish.as_ref().map(|operation| {
-                (
+                let mut simplified_operation = utilities::simplify_operation(
+                    operation,
                     channel_name,
-                    utilities::simplify_operation(operation, channel_name),
-                )
+                    channel,
+                    asyncapi.default_content_type.as_deref(),
+                    protocol,
+                )?;
+                if channel.subscribe.is_some() && operation.operation_id.is_none() {
+                    simplified_operation.unique_id += "_publish";
+                }
+                // we receive the request, so the reply is sent on the subscribe operation
+                simplified_operation.reply =
+                    utilities::simplify_reply(asyncapi, operation, protocol, |reply_channel| {
+                        reply_channel.subscribe.as_ref()
+                    })?;
+                Ok((channel_name, simplified_operation))
             })
         })
         .collect()
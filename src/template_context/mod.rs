@@ -17,17 +17,33 @@ pub fn create_template_context<'a>(spec: &'a AsyncAPI) -> Result<TemplateContext
         ReferenceOr::Reference { reference: _ } => None.unwrap(),
     };
 
-    let publish_channels = channel_operations::get_publish_channels_operations(spec);
-    let subscribe_channels = channel_operations::get_subscribe_channels_operations(spec);
+    let protocol = utilities::normalize_protocol(&server.protocol)?;
+    let protocol_version =
+        utilities::normalize_protocol_version(&server.protocol, server.protocol_version.as_deref());
+    let auth_methods = utilities::auth_methods(spec, server, &protocol)?;
+    let server_url = utilities::server_url(server);
+    let tls = utilities::server_tls(server);
+    let publish_channels = channel_operations::get_publish_channels_operations(spec, &protocol)?;
+    let subscribe_channels =
+        channel_operations::get_subscribe_channels_operations(spec, &protocol)?;
     let model: Model =
         model::extract_model_from_channels(publish_channels.clone(), subscribe_channels.clone());
+    let content_types =
+        model::extract_content_types(publish_channels.iter().chain(subscribe_channels.iter()));
     let template_context: TemplateContext<'a> = TemplateContext {
         server,
+        server_url,
         subscribe_channels,
         publish_channels,
         title: &spec.info.title,
+        version: &spec.info.version,
         description: &spec.info.description,
         model,
+        content_types,
+        protocol,
+        protocol_version,
+        auth_methods,
+        tls,
     };
     Ok(template_context)
 }
@@ -2,6 +2,21 @@ use crate::parser::json_schema_parser::types::RustSchemaRepresentation;
 
 use super::{Model, SimplifiedOperation};
 
+// collects the content types of all messages without duplicates
+pub fn extract_content_types<'a>(
+    channels: impl Iterator<Item = &'a (&'a String, SimplifiedOperation)>,
+) -> Vec<String> {
+    let mut content_types: Vec<String> = vec![];
+    for (_, operation) in channels {
+        for message in &operation.messages {
+            if !content_types.contains(&message.content_type) {
+                content_types.push(message.content_type.clone());
+            }
+        }
+    }
+    content_types
+}
+
 // extracts all message models from the pub and sub channels, makes sure no model with the same identifyer is added twice
 pub fn extract_model_from_channels(
     pub_channels: Vec<(&String, SimplifiedOperation)>,
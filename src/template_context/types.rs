@@ -1,5 +1,5 @@
 use crate::{
-    asyncapi_model::{Message, Operation, Server},
+    asyncapi_model::{ChannelBinding, Message, Operation, Server},
     parser::json_schema_parser::types::RustSchemaRepresentation,
 };
 use gtmpl::Value;
@@ -10,11 +10,25 @@ use std::string::*;
 #[derive(Serialize, Debug)]
 pub struct TemplateContext<'a> {
     pub title: &'a String,
+    // `info.version` of the spec, reported by the build info endpoint of the service
+    pub version: &'a String,
     pub description: &'a Option<String>,
     pub server: &'a Server,
+    // the url of the server with the default values of its variables, see `server_url`
+    pub server_url: String,
     pub subscribe_channels: Vec<(&'a String, SimplifiedOperation)>,
     pub publish_channels: Vec<(&'a String, SimplifiedOperation)>,
     pub model: Model,
+    // all content types used by messages, to pull in the matching codecs
+    pub content_types: Vec<String>,
+    // broker backend of the generated service (e.g. nats or kafka), see `normalize_protocol`
+    pub protocol: String,
+    // e.g. 3.1.1 or 5 for mqtt, see `normalize_protocol_version`
+    pub protocol_version: String,
+    // how the service authenticates with the broker, the alternatives of the server security requirements, see `auth_methods`
+    pub auth_methods: Vec<String>,
+    // whether the server is only reachable with TLS, e.g. `mqtts` or `kafka-secure`, see `server_tls`
+    pub tls: bool,
 }
 
 #[derive(Serialize, Debug)]
@@ -29,6 +43,20 @@ pub struct SimplifiedOperation {
     pub original_operation: Operation,
     // array, da es eine oder mehrere messages geben kann
     pub messages: Vec<SimplifiedMessage>,
+    // the operation answering this one, if it is part of a request/reply exchange (see `simplify_reply`)
+    pub reply: Option<Box<SimplifiedOperation>>,
+    // broker backend of the generated service, separately rendered templates have no access to the root context
+    pub protocol: String,
+    // bindings of the channel the operation belongs to, e.g. the exchange and queue of an amqp channel
+    pub channel_bindings: Option<ChannelBinding>,
+    // values of the `groupId` and `clientId` schemas of the kafka operation binding
+    pub kafka_group_id: Option<String>,
+    pub kafka_client_id: Option<String>,
+    // `name=value` pairs of the query parameters and handshake headers of the websocket channel binding
+    pub ws_query: Option<String>,
+    pub ws_headers: Option<String>,
+    // redis channels with a `stream` binding or `x-redis-stream: true` are redis streams instead of pub/sub channels
+    pub redis_stream: bool,
     // pub multiple_messages_enum: Option<MultiStructEnum>,
 }
 #[derive(Serialize, Debug, Clone)]
@@ -45,6 +73,14 @@ pub struct SimplifiedMessage {
     pub original_message: Message,
     pub payload: Option<RustSchemaRepresentation>,
     pub payload_schema: Option<String>,
+    // runtime expression pointing to the correlation id (e.g. `$message.header#/correlation_id`)
+    pub correlation_id_location: Option<String>,
+    // media type used to encode/decode the payload, falls back to the `defaultContentType` of the spec
+    pub content_type: String,
+    // format of the payload schema (e.g. json_schema or avro), see `normalize_schema_format`
+    pub schema_format: String,
+    // value of the `key` schema of the kafka message binding, used if producers are called without key
+    pub kafka_key: Option<String>,
 }
 
 /// FIXME: these are just a quick workaround until gtmpl::Value supports `From<impl Serialize> for gtmpl::Value`
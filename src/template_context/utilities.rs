@@ -1,14 +1,36 @@
 use crate::{
-    asyncapi_model::{Message, Operation, OperationMessageType, Payload, ReferenceOr, Schema},
+    asyncapi_model::{
+        AsyncAPI, Channel, Message, MessageTrait, Operation, OperationBinding,
+        OperationMessageType, OperationTrait, Payload, ReferenceOr, Schema, SecurityScheme, Server,
+    },
     parser::{
+        avro_schema_parser::parse_avro_schema_to_rust_type,
         common::validate_identifier_string,
-        json_schema_parser::{parse_json_schema_to_rust_type, types::RustSchemaRepresentation},
+        json_schema_parser::{
+            parse_json_schema_to_rust_type, types::RustSchemaRepresentation, SchemaParserError,
+        },
+        protobuf_schema_parser::parse_protobuf_schema_to_rust_type,
     },
 };
 
 use super::types::{SimplifiedMessage, SimplifiedOperation};
+use std::io;
+
+/// content type used if neither the message nor the spec define one
+pub const DEFAULT_CONTENT_TYPE: &str = "application/json";
+/// content type of avro binary encoded payloads
+pub const AVRO_CONTENT_TYPE: &str = "application/vnd.apache.avro";
+/// content type of protobuf encoded payloads
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
 
-pub fn simplify_operation(operation: &Operation, channel_name: &str) -> SimplifiedOperation {
+pub fn simplify_operation(
+    operation: &Operation,
+    channel_name: &str,
+    channel: &Channel,
+    default_content_type: Option<&str>,
+    protocol: &str,
+) -> Result<SimplifiedOperation, io::Error> {
+    let operation = &with_trait_bindings(operation);
     let unique_id = operation
         .operation_id
         .clone()
@@ -18,68 +40,636 @@ pub fn simplify_operation(operation: &Operation, channel_name: &str) -> Simplifi
         Some(operation_message) => match operation_message {
             OperationMessageType::Map(map) => map
                 .into_iter()
-                .map(|(_, m)| simplify_message(m, &unique_id))
-                .collect(),
+                .map(|(_, m)| simplify_message(m, &unique_id, default_content_type))
+                .collect::<Result<_, _>>()?,
             OperationMessageType::Single(message_or_ref) => {
-                vec![simplify_message(message_or_ref, &unique_id)]
+                vec![simplify_message(
+                    message_or_ref,
+                    &unique_id,
+                    default_content_type,
+                )?]
             }
             OperationMessageType::OneOf(multiple_messages) => multiple_messages
                 .one_of
                 .iter()
-                .map(|m| simplify_message(m, &unique_id))
-                .collect(),
+                .map(|m| simplify_message(m, &unique_id, default_content_type))
+                .collect::<Result<_, _>>()?,
         },
         _ => vec![],
     };
     // let message_enum =
     //     build_multi_message_enum(&messages, format!("{}Message", unique_id).as_str());
-    SimplifiedOperation {
+    let kafka_binding = match &operation.bindings {
+        Some(ReferenceOr::Item(bindings)) => bindings.kafka.as_ref(),
+        _ => None,
+    };
+    let ws_binding = match &channel.bindings {
+        Some(ReferenceOr::Item(bindings)) => bindings.ws.as_ref(),
+        _ => None,
+    };
+    let redis_binding = match &channel.bindings {
+        Some(ReferenceOr::Item(bindings)) => bindings.redis.as_ref(),
+        _ => None,
+    };
+    let redis_stream = redis_binding.and_then(|binding| binding.typ.as_deref()) == Some("stream")
+        || channel.extensions.get("x-redis-stream") == Some(&serde_json::Value::Bool(true));
+    Ok(SimplifiedOperation {
         unique_id,
         original_operation: operation.clone(),
         messages,
+        reply: None,
+        protocol: protocol.to_string(),
+        channel_bindings: match &channel.bindings {
+            Some(ReferenceOr::Item(bindings)) => Some(bindings.clone()),
+            _ => None,
+        },
+        kafka_group_id: kafka_binding
+            .and_then(|binding| binding.group_id.as_ref())
+            .and_then(schema_value),
+        kafka_client_id: kafka_binding
+            .and_then(|binding| binding.client_id.as_ref())
+            .and_then(schema_value),
+        ws_query: ws_binding
+            .and_then(|binding| binding.query.as_ref())
+            .and_then(schema_property_values),
+        ws_headers: ws_binding
+            .and_then(|binding| binding.headers.as_ref())
+            .and_then(schema_property_values),
+        redis_stream,
         // multiple_messages_enum: message_enum,
+    })
+}
+
+/// the operation with the bindings of its traits, e.g. the kafka `clientId` shared by all operations,
+/// a binding the operation defines itself takes precedence over the one of a trait
+pub fn with_trait_bindings(operation: &Operation) -> Operation {
+    let mut bindings = match &operation.bindings {
+        Some(ReferenceOr::Item(bindings)) => bindings.clone(),
+        _ => OperationBinding::default(),
+    };
+    for operation_trait in &operation.traits {
+        if let ReferenceOr::Item(OperationTrait {
+            bindings: Some(ReferenceOr::Item(trait_bindings)),
+            ..
+        }) = operation_trait
+        {
+            bindings.http = bindings.http.or_else(|| trait_bindings.http.clone());
+            bindings.ws = bindings.ws.or_else(|| trait_bindings.ws.clone());
+            bindings.kafka = bindings.kafka.or_else(|| trait_bindings.kafka.clone());
+            bindings.anypointmq = bindings
+                .anypointmq
+                .or_else(|| trait_bindings.anypointmq.clone());
+            bindings.amqp = bindings.amqp.or_else(|| trait_bindings.amqp.clone());
+            bindings.amqp1 = bindings.amqp1.or_else(|| trait_bindings.amqp1.clone());
+            bindings.mqtt = bindings.mqtt.or_else(|| trait_bindings.mqtt.clone());
+            bindings.mqtt5 = bindings.mqtt5.or_else(|| trait_bindings.mqtt5.clone());
+            bindings.nats = bindings.nats.or_else(|| trait_bindings.nats.clone());
+            bindings.jms = bindings.jms.or_else(|| trait_bindings.jms.clone());
+            bindings.sns = bindings.sns.or_else(|| trait_bindings.sns.clone());
+            bindings.solace = bindings.solace.or_else(|| trait_bindings.solace.clone());
+            bindings.sqs = bindings.sqs.or_else(|| trait_bindings.sqs.clone());
+            bindings.stomp = bindings.stomp.or_else(|| trait_bindings.stomp.clone());
+            bindings.redis = bindings.redis.or_else(|| trait_bindings.redis.clone());
+            bindings.mercure = bindings.mercure.or_else(|| trait_bindings.mercure.clone());
+        }
+    }
+    let mut operation = operation.clone();
+    if bindings != OperationBinding::default() {
+        operation.bindings = Some(ReferenceOr::Item(bindings));
     }
+    operation
+}
+
+/// resolves the operation answering `operation`
+/// an amqp request carries a `replyTo` in its operation or message binding and a `correlationId` in its messages,
+/// its reply channel is the channel named by the operation binding or else the only channel answering with a `correlationId`
+/// as a fallback, e.g. for other protocols, the reply channel is named in the `x-reply-channel` extension
+/// `reply_operation` picks the operation of the reply channel, e.g. `publish` if we are the requester
+pub fn simplify_reply<'a>(
+    asyncapi: &'a AsyncAPI,
+    operation: &Operation,
+    protocol: &str,
+    reply_operation: impl Fn(&'a Channel) -> Option<&'a Operation>,
+) -> Result<Option<Box<SimplifiedOperation>>, io::Error> {
+    let derived = match protocol {
+        "amqp" => amqp_reply_channel(asyncapi, operation, &reply_operation),
+        _ => Ok(None),
+    };
+    let reply_channel_name = match (derived, operation.extensions.get("x-reply-channel")) {
+        (Ok(Some(name)), _) => name,
+        (_, Some(serde_json::Value::String(name))) => name.as_str(),
+        (_, Some(_)) => return Err(invalid_spec("x-reply-channel value is not a string")),
+        (Err(e), None) => return Err(e),
+        (Ok(None), None) => return Ok(None),
+    };
+    let reply_channel = asyncapi.channels.get(reply_channel_name).ok_or_else(|| {
+        invalid_spec(format!(
+            "Reply channel {} does not exist",
+            reply_channel_name
+        ))
+    })?;
+    let reply = match reply_operation(reply_channel) {
+        Some(reply) => simplify_operation(
+            reply,
+            reply_channel_name,
+            reply_channel,
+            asyncapi.default_content_type.as_deref(),
+            protocol,
+        )?,
+        None => {
+            return Err(invalid_spec(format!(
+                "Reply channel {} has no operation to answer the request",
+                reply_channel_name
+            )))
+        }
+    };
+    if reply.messages.is_empty() {
+        return Err(invalid_spec(format!(
+            "Reply channel {} has no message",
+            reply_channel_name
+        )));
+    }
+    Ok(Some(Box::new(reply)))
+}
+
+/// the reply channel of an amqp request, if `operation` is one
+fn amqp_reply_channel<'a>(
+    asyncapi: &'a AsyncAPI,
+    operation: &Operation,
+    reply_operation: impl Fn(&'a Channel) -> Option<&'a Operation>,
+) -> Result<Option<&'a str>, io::Error> {
+    let operation = &with_trait_bindings(operation);
+    let reply_to = match &operation.bindings {
+        Some(ReferenceOr::Item(bindings)) => {
+            bindings.amqp.as_ref().and_then(|b| b.reply_to.as_deref())
+        }
+        _ => None,
+    };
+    let messages = operation_messages(operation);
+    let message_reply_to = messages.iter().any(|message| match &message.bindings {
+        Some(ReferenceOr::Item(bindings)) => {
+            bindings.amqp.as_ref().is_some_and(|b| b.reply_to.is_some())
+        }
+        _ => false,
+    });
+    let correlated = !messages.is_empty() && messages.iter().all(|m| m.correlation_id.is_some());
+    if !correlated || (reply_to.is_none() && !message_reply_to) {
+        return Ok(None);
+    }
+    // a named queue that is a channel of the spec, e.g. not the direct reply-to pseudo queue of rabbitmq
+    if let Some((name, _)) = reply_to.and_then(|name| asyncapi.channels.get_key_value(name)) {
+        return Ok(Some(name.as_str()));
+    }
+    let candidates: Vec<&str> = asyncapi
+        .channels
+        .iter()
+        .filter(|(_, channel)| match reply_operation(channel) {
+            Some(reply) => {
+                let replies = operation_messages(reply);
+                !replies.is_empty() && replies.iter().all(|m| m.correlation_id.is_some())
+            }
+            None => false,
+        })
+        .map(|(name, _)| name.as_str())
+        .collect();
+    match candidates.as_slice() {
+        [name] => Ok(Some(name)),
+        [] => Err(invalid_spec(format!(
+            "Request {} has no channel answering it with a correlationId, name it with x-reply-channel",
+            operation.operation_id.as_deref().unwrap_or_default()
+        ))),
+        _ => Err(invalid_spec(format!(
+            "Request {} could be answered on any of {}, name the reply channel with x-reply-channel",
+            operation.operation_id.as_deref().unwrap_or_default(),
+            candidates.join(", ")
+        ))),
+    }
+}
+
+/// the messages of an operation defined in the spec itself
+fn operation_messages(operation: &Operation) -> Vec<&Message> {
+    let messages: Vec<&ReferenceOr<Message>> = match &operation.message {
+        Some(OperationMessageType::Map(map)) => map.values().collect(),
+        Some(OperationMessageType::Single(message)) => vec![message],
+        Some(OperationMessageType::OneOf(multiple_messages)) => {
+            multiple_messages.one_of.iter().collect()
+        }
+        None => vec![],
+    };
+    messages
+        .into_iter()
+        .filter_map(|message| match message {
+            ReferenceOr::Item(message) => Some(message),
+            _ => None,
+        })
+        .collect()
+}
+
+/// an error for a spec the service cannot be generated from
+fn invalid_spec(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
 }
 
 pub fn simplify_message(
     message_or_ref: &ReferenceOr<Message>,
     unique_parent_id: &str,
-) -> SimplifiedMessage {
+    default_content_type: Option<&str>,
+) -> Result<SimplifiedMessage, io::Error> {
     if let ReferenceOr::Item(message) = message_or_ref {
+        let schema_format = normalize_schema_format(message.schema_format.as_deref());
+        let content_type = match schema_format.as_str() {
+            // avro payloads are always avro binary encoded, the codec needs the avro schema anyway
+            "avro" => AVRO_CONTENT_TYPE.to_string(),
+            "protobuf" => PROTOBUF_CONTENT_TYPE.to_string(),
+            _ => normalize_content_type(
+                message
+                    .content_type
+                    .as_deref()
+                    .or(default_content_type)
+                    .unwrap_or(DEFAULT_CONTENT_TYPE),
+            ),
+        };
         let mut unique_id: String = "".to_string();
         let payload = match &message.payload {
-            Some(schema) => {
-                if let Payload::Schema(schema) = schema {
-                    let message_name = match &message.name {
-                        Some(name) => name.to_string(),
-                        None => {
-                            format!("{}Message", unique_parent_id)
-                        }
-                    };
-                    unique_id = validate_identifier_string(&message_name, false);
-                    let simplified_schema = simplify_schema(schema, &unique_id);
-                    Some(simplified_schema)
-                } else {
-                    None
+            Some(payload) => {
+                let message_name = match &message.name {
+                    Some(name) => name.to_string(),
+                    None => {
+                        format!("{}Message", unique_parent_id)
+                    }
+                };
+                match (schema_format.as_str(), payload) {
+                    ("avro", payload) => {
+                        unique_id = validate_identifier_string(&message_name, false);
+                        Some(simplify_avro_schema(message, payload, &unique_id)?)
+                    }
+                    ("protobuf", Payload::Any(serde_json::Value::String(source))) => {
+                        unique_id = validate_identifier_string(&message_name, false);
+                        Some(
+                            parse_protobuf_schema_to_rust_type(source, &message_name).map_err(
+                                |e| {
+                                    invalid_spec(format!(
+                                        "Failed to parse proto definition of {}: {}",
+                                        unique_id, e
+                                    ))
+                                },
+                            )?,
+                        )
+                    }
+                    ("protobuf", _) => {
+                        return Err(invalid_spec(format!(
+                            "Protobuf payload of {} is not a .proto definition",
+                            message_name
+                        )))
+                    }
+                    (_, Payload::Schema(schema)) => {
+                        unique_id = validate_identifier_string(&message_name, false);
+                        let simplified_schema = match content_type.as_str() {
+                            // raw bytes are passed through as they are, regardless of the schema
+                            "application/octet-stream" => RustSchemaRepresentation {
+                                unique_id: unique_id.clone(),
+                                original_key: message_name,
+                                struct_reference: "Vec<u8>".to_string(),
+                                model_definition: "".to_string(),
+                                related_models: vec![],
+                                model_type: "primitive".to_string(),
+                            },
+                            _ => simplify_schema(schema, &unique_id).map_err(|e| {
+                                invalid_spec(format!("Payload of {}: {}", unique_id, e))
+                            })?,
+                        };
+                        Some(simplified_schema)
+                    }
+                    _ => None,
                 }
             }
             None => None,
         };
-        SimplifiedMessage {
+        let correlation_id_location = match &message.correlation_id {
+            Some(ReferenceOr::Item(correlation_id)) => Some(correlation_id.location.clone()),
+            _ => None,
+        };
+        // the key binding of the message itself takes precedence over the one of a trait
+        let trait_bindings =
+            message
+                .traits
+                .iter()
+                .filter_map(|message_trait| match message_trait {
+                    ReferenceOr::Item(MessageTrait {
+                        bindings: Some(bindings),
+                        ..
+                    }) => Some(bindings),
+                    _ => None,
+                });
+        let kafka_key = message
+            .bindings
+            .iter()
+            .chain(trait_bindings)
+            .find_map(|bindings| match bindings {
+                ReferenceOr::Item(bindings) => bindings
+                    .kafka
+                    .as_ref()
+                    .and_then(|binding| binding.key.as_ref()),
+                _ => None,
+            })
+            .and_then(schema_value);
+        Ok(SimplifiedMessage {
             unique_id,
             original_message: message.clone(),
             payload,
             payload_schema: message.payload_schema.clone(),
-        }
+            correlation_id_location,
+            content_type,
+            schema_format,
+            kafka_key,
+        })
     } else {
         panic!("Refs should be resolved by now");
     }
 }
 
-pub fn simplify_schema(schema: &Schema, unique_parent_id: &str) -> RustSchemaRepresentation {
+pub fn simplify_schema(
+    schema: &Schema,
+    unique_parent_id: &str,
+) -> Result<RustSchemaRepresentation, SchemaParserError> {
     let schema_name = match &schema.schema_data.name {
         Some(name) => validate_identifier_string(name, false),
         None => validate_identifier_string(unique_parent_id, false),
     };
-    parse_json_schema_to_rust_type(schema, &schema_name).unwrap()
+    parse_json_schema_to_rust_type(schema, &schema_name)
+}
+
+/// parses an avro payload, the raw schema is used since avro schemas are no valid json schemas
+pub fn simplify_avro_schema(
+    message: &Message,
+    payload: &Payload,
+    unique_parent_id: &str,
+) -> Result<RustSchemaRepresentation, io::Error> {
+    let invalid_schema = |e: &dyn std::fmt::Display| {
+        invalid_spec(format!(
+            "Failed to parse avro schema of {}: {}",
+            unique_parent_id, e
+        ))
+    };
+    let schema = match (&message.payload_schema, payload) {
+        (Some(payload_schema), _) => {
+            serde_json::from_str(payload_schema).map_err(|e| invalid_schema(&e))?
+        }
+        // primitive avro schemas are plain strings, e.g. "string"
+        (None, Payload::Any(schema)) => schema.clone(),
+        (None, Payload::Schema(schema)) => {
+            serde_json::to_value(schema).map_err(|e| invalid_schema(&e))?
+        }
+    };
+    parse_avro_schema_to_rust_type(&schema, unique_parent_id).map_err(|e| invalid_schema(&e))
+}
+
+/// maps a schema format to the schema parser used for the payload
+/// the default AsyncAPI schema format and unsupported formats are parsed as json schema
+pub fn normalize_schema_format(schema_format: Option<&str>) -> String {
+    let schema_format = match schema_format {
+        Some(schema_format) => schema_format,
+        None => return "json_schema".to_string(),
+    };
+    let media_type = schema_format
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    match media_type.as_str() {
+        "application/vnd.aai.asyncapi"
+        | "application/vnd.aai.asyncapi+json"
+        | "application/vnd.aai.asyncapi+yaml"
+        | "application/schema+json"
+        | "application/schema+yaml" => "json_schema",
+        // normalized to json schema by the preprocessor
+        "application/vnd.oai.openapi"
+        | "application/vnd.oai.openapi+json"
+        | "application/vnd.oai.openapi+yaml"
+        | "application/raml+yaml" => "json_schema",
+        "application/vnd.apache.avro"
+        | "application/vnd.apache.avro+json"
+        | "application/vnd.apache.avro+yaml" => "avro",
+        "application/vnd.google.protobuf" | "application/x-protobuf" | "application/protobuf" => {
+            "protobuf"
+        }
+        _ => {
+            println!(
+                "⚠️ Unsupported schema format {}, parsing payload as json schema",
+                schema_format
+            );
+            "json_schema"
+        }
+    }
+    .to_string()
+}
+
+/// maps a media type to the content type of the codec used by the generated service
+/// unsupported content types fall back to json, as schema parsers MUST use their default content type in that case
+pub fn normalize_content_type(content_type: &str) -> String {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    match media_type.as_str() {
+        "application/json" | "text/json" => DEFAULT_CONTENT_TYPE,
+        _ if media_type.ends_with("+json") => DEFAULT_CONTENT_TYPE,
+        "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+            "application/msgpack"
+        }
+        "application/cbor" => "application/cbor",
+        _ if media_type.ends_with("+cbor") => "application/cbor",
+        "text/plain" => "text/plain",
+        "application/octet-stream" => "application/octet-stream",
+        _ => {
+            println!(
+                "⚠️ Unsupported content type {}, falling back to {}",
+                content_type, DEFAULT_CONTENT_TYPE
+            );
+            DEFAULT_CONTENT_TYPE
+        }
+    }
+    .to_string()
+}
+
+/// maps the protocol of a server to the broker backend of the generated service
+/// http(s) servers are streaming endpoints consumed as server-sent events by the mercure backend
+pub fn normalize_protocol(protocol: &str) -> Result<String, io::Error> {
+    let backend = match protocol.to_lowercase().as_str() {
+        "nats" | "tls" | "nats+tls" => "nats",
+        "kafka" | "kafka-secure" => "kafka",
+        "mqtt" | "mqtts" | "secure-mqtt" | "mqtt5" => "mqtt",
+        "amqp" | "amqps" => "amqp",
+        "ws" | "wss" => "ws",
+        "mercure" | "http" | "https" | "sse" => "mercure",
+        "redis" | "rediss" => "redis",
+        _ => {
+            return Err(invalid_spec(format!(
+                "Unsupported protocol {}, supported are nats, kafka, mqtt, amqp, ws, redis, mercure and http(s) event streams",
+                protocol
+            )))
+        }
+    };
+    Ok(backend.to_string())
+}
+
+/// the url of the server with its variables like `{port}` replaced by their default value, so the service can connect to it
+pub fn server_url(server: &Server) -> String {
+    server.variables.iter().fold(
+        server.url.clone(),
+        |url, (name, variable)| match &variable.default {
+            Some(default) => url.replace(&format!("{{{}}}", name), default),
+            None => url,
+        },
+    )
+}
+
+/// percent-encodes all but the unreserved characters of RFC 3986, so values may contain `&`, `=` or spaces
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// whether the server is only reachable with tls, by its protocol or the scheme of its url
+pub fn server_tls(server: &Server) -> bool {
+    let scheme = server.url.split_once("://").map(|(scheme, _)| scheme);
+    [Some(server.protocol.as_str()), scheme]
+        .into_iter()
+        .flatten()
+        .any(|protocol| {
+            matches!(
+                protocol.to_lowercase().as_str(),
+                "tls" | "nats+tls" | "kafka-secure" | "mqtts" | "secure-mqtt" | "ssl" | "amqps"
+            )
+        })
+}
+
+/// the protocol version the generated service speaks, the mqtt backend supports 3.1.1 and 5
+pub fn normalize_protocol_version(protocol: &str, protocol_version: Option<&str>) -> String {
+    match (protocol.to_lowercase().as_str(), protocol_version) {
+        ("mqtt5", _) => "5".to_string(),
+        ("mqtt" | "mqtts" | "secure-mqtt", Some(version)) if version.starts_with('5') => {
+            "5".to_string()
+        }
+        ("mqtt" | "mqtts" | "secure-mqtt", _) => "3.1.1".to_string(),
+        (_, version) => version.unwrap_or_default().to_string(),
+    }
+}
+
+/// how the generated nats service authenticates for a security scheme of the server,
+/// `None` for schemes nats has no equivalent for
+pub fn nats_auth_method(scheme: &SecurityScheme) -> Option<&'static str> {
+    match scheme {
+        SecurityScheme::UserPassword { .. } => Some("user_password"),
+        SecurityScheme::ApiKey { .. } | SecurityScheme::HttpApiKey { .. } => Some("token"),
+        // a `.creds` file holds the user jwt and the nkey seed signing the server nonce
+        SecurityScheme::Http {
+            scheme,
+            bearer_format,
+            ..
+        } if scheme.eq_ignore_ascii_case("bearer") => {
+            match bearer_format.as_deref().map(str::to_lowercase).as_deref() {
+                Some("jwt") => Some("creds"),
+                _ => Some("token"),
+            }
+        }
+        SecurityScheme::AsymmetricEncryption { .. } => Some("nkey"),
+        // the client certificate of mutual tls identifies the service
+        SecurityScheme::X509 { .. } => Some("x509"),
+        _ => None,
+    }
+}
+
+/// the authentication methods of the security requirements of the server, alternatives in the order of the spec
+/// each requirement is mapped to its first scheme the generated service supports
+pub fn auth_methods(
+    spec: &AsyncAPI,
+    server: &Server,
+    protocol: &str,
+) -> Result<Vec<String>, io::Error> {
+    let mut methods = vec![];
+    for requirement in &server.security {
+        let mut method = None;
+        for name in requirement.values.keys() {
+            let scheme = spec
+                .components
+                .as_ref()
+                .and_then(|components| components.security_schemes.get(name));
+            let scheme = match scheme {
+                Some(ReferenceOr::Item(scheme)) => scheme,
+                _ => {
+                    return Err(invalid_spec(format!(
+                        "Security scheme {} does not exist",
+                        name
+                    )))
+                }
+            };
+            method = match (protocol, scheme) {
+                ("nats", scheme) => nats_auth_method(scheme),
+                ("kafka" | "mqtt" | "amqp", SecurityScheme::X509 { .. }) => Some("x509"),
+                _ => None,
+            };
+            if method.is_some() {
+                break;
+            }
+        }
+        match method {
+            Some(method) if !methods.contains(&method.to_string()) => methods.push(method.to_string()),
+            Some(_) => (),
+            None => println!(
+                "⚠️ Unsupported security requirement {}, the {} service will not authenticate with it",
+                requirement.values.keys().cloned().collect::<Vec<_>>().join(", "),
+                protocol
+            ),
+        }
+    }
+    Ok(methods)
+}
+
+/// the value a binding schema pins down, e.g. `groupId: { type: string, enum: ['myGroupId'] }`
+/// uses `const`, the first `enum` value, `default` or `example`, in this order
+pub fn schema_value(schema: &Schema) -> Option<String> {
+    json_schema_value(&serde_json::to_value(schema).ok()?)
+}
+
+fn json_schema_value(schema: &serde_json::Value) -> Option<String> {
+    let value = schema
+        .get("const")
+        .or_else(|| schema.get("enum").and_then(|values| values.get(0)))
+        .or_else(|| schema.get("default"))
+        .or_else(|| schema.get("example"))?;
+    match value {
+        serde_json::Value::String(value) => Some(value.clone()),
+        serde_json::Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// the values the properties of an object schema pin down as percent-encoded `name=value` pairs joined by `&`,
+/// e.g. the query parameters of a websocket channel binding, properties without value are left out
+pub fn schema_property_values(schema: &Schema) -> Option<String> {
+    let schema = serde_json::to_value(schema).ok()?;
+    let pairs: Vec<String> = schema
+        .get("properties")?
+        .as_object()?
+        .iter()
+        .filter_map(|(name, property)| {
+            json_schema_value(property)
+                .map(|value| format!("{}={}", percent_encode(name), percent_encode(&value)))
+        })
+        .collect();
+    match pairs.is_empty() {
+        true => None,
+        false => Some(pairs.join("&")),
+    }
 }
@@ -1,38 +1,209 @@
 ################General Config################
 
+# a .toml, .yaml or .yml file with further settings, the environment wins over it and it wins over this file
+#CONFIG_FILE = "config.toml"
 SERVICE_PORT = "8080"
-SERVER_URL = "{{ .server.url }}"
+# "memory" runs the service on an in-process broker, without connecting to one
+SERVER_URL = "{{ .server_url }}"
 LOG_LEVEL = "DEBUG"
+# "pretty" text or one "json" object per line, both with the channel, operation id, message id, correlation id and trace id of the handled message
+LOG_FORMAT = "pretty"
+# payload fields marked `format: password` or `writeOnly` in the schema and the comma separated LOG_REDACT_FIELDS are logged as [REDACTED]
+LOG_REDACT = true
+LOG_REDACT_FIELDS = ""
 OPA_RULES= "path/to/admin/policy"
 TRACING_ENABLED = false
+# the OTLP collector the spans are exported to over grpc
+OTEL_EXPORTER_OTLP_ENDPOINT = "http://localhost:4317"
 SCHEMA_VALIDATION_ENABLED = true
+REQUEST_TIMEOUT_MS = 5000
+# how long the handled messages get to finish after SIGINT or SIGTERM
+SHUTDOWN_TIMEOUT_MS = 20000
+# /healthz fails once a message has been handled for longer, the handler is stuck
+LIVENESS_HANDLER_TIMEOUT_MS = 300000
+{{ if and (eq .protocol "nats") .auth_methods }}
+# the server requires authentication, set the secrets of one of its security requirements
+# or the *_FILE variables pointing to files holding them (e.g. NATS_PASSWORD_FILE = "/run/secrets/nats_password")
+{{ range .auth_methods }}
+{{ if eq . "user_password" }}
+NATS_USER = ""
+NATS_PASSWORD = ""
+{{ else if eq . "token" }}
+NATS_TOKEN = ""
+{{ else if eq . "nkey" }}
+NATS_NKEY_SEED = ""
+{{ else if eq . "creds" }}
+#NATS_CREDS_FILE = "path/to/user.creds"
+{{ end }}
+{{ end }}
+{{ end }}
+{{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+# connect with tls only, CA bundle instead of the system certificates (e.g. of a self-signed CA),
+# name the server certificate is verified against instead of the host of SERVER_URL
+TLS_REQUIRED = {{ .tls }}
+#TLS_CA_FILE = "path/to/ca.pem"
+#TLS_SERVER_NAME = "broker.example.com"
+# client certificate and key for mutual tls
+#TLS_CERT_FILE = "path/to/client.pem"
+#TLS_KEY_FILE = "path/to/client-key.pem"
+{{ range .auth_methods }}{{ if eq . "x509" }}
+TLS_CERT_FILE = ""
+TLS_KEY_FILE = ""
+{{ end }}{{ end }}
+{{ end }}
+{{ if eq .protocol "kafka" }}
+# offset of consumer groups without committed offset, earliest or latest
+KAFKA_AUTO_OFFSET_RESET = "earliest"
+{{ end }}
+{{ if eq .protocol "amqp" }}
+# unacked messages a consumer receives at once
+AMQP_PREFETCH = 10
+{{ end }}
+{{ if eq .protocol "ws" }}
+# server accepts connections on the channel paths, client connects to the channel paths of SERVER_URL
+WS_MODE = "server"
+WS_MAX_BACKOFF_MS = 30000
+{{ end }}
+{{ if eq .protocol "mercure" }}
+# token for publishing updates, or the key to sign one with (HS256), the subscriber token is only needed for private updates
+#MERCURE_PUBLISHER_JWT = ""
+MERCURE_PUBLISHER_JWT_KEY = "!ChangeThisMercureHubJWTSecretKey!"
+#MERCURE_SUBSCRIBER_JWT_KEY = ""
+# wait before resuming a closed event stream, unless the hub sends a retry
+MERCURE_RETRY_MS = 3000
+{{ end }}
+{{ if eq .protocol "mqtt" }}
+{{ if key_exists .server "bindings" "mqtt" "clientId" }}
+MQTT_CLIENT_ID = "{{ .server.bindings.mqtt.clientId }}"
+{{ else }}
+#MQTT_CLIENT_ID = "{{ to_lower (replace .title " " "_") }}"
+{{ end }}
+{{ if key_exists .server "bindings" "mqtt" "cleanSession" }}
+MQTT_CLEAN_SESSION = {{ .server.bindings.mqtt.cleanSession }}
+{{ end }}
+{{ if key_exists .server "bindings" "mqtt" "keepAlive" }}
+MQTT_KEEP_ALIVE = {{ .server.bindings.mqtt.keepAlive }}
+{{ end }}
+{{ if key_exists .server "bindings" "mqtt" "lastWill" "topic" }}
+MQTT_LAST_WILL_TOPIC = "{{ .server.bindings.mqtt.lastWill.topic }}"
+MQTT_LAST_WILL_MESSAGE = "{{ if key_exists .server "bindings" "mqtt" "lastWill" "message" }}{{ .server.bindings.mqtt.lastWill.message }}{{ end }}"
+MQTT_LAST_WILL_QOS = {{ if key_exists .server "bindings" "mqtt" "lastWill" "qos" }}{{ .server.bindings.mqtt.lastWill.qos }}{{ else }}0{{ end }}
+MQTT_LAST_WILL_RETAIN = {{ if key_exists .server "bindings" "mqtt" "lastWill" "retain" }}{{ .server.bindings.mqtt.lastWill.retain }}{{ else }}false{{ end }}
+{{ end }}
+{{ end }}
 
 ################Channel wise Config################
 {{ range .subscribe_channels }}
 ################{{ (index . 1).unique_id }}################
-        {{ if (index . 1).original_operation.bindings }}
-                {{ if (index . 1).original_operation.bindings.nats.queue }}
+        {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "queue" }}
 {{ (index . 1).unique_id}}_QUEUE = "{{ (index . 1).original_operation.bindings.nats.queue}}"
-                {{else}}
+        {{ else if key_exists (index . 1) "original_operation" "bindings" "nats" "streamname" }}
 {{ (index . 1).unique_id}}_STREAM = "{{ (index . 1).original_operation.bindings.nats.streamname}}"
-                {{ end }}
         {{ end }}
+        {{ if eq (index . 1).protocol "mqtt" }}
+{{ (index . 1).unique_id }}_QOS = {{ if key_exists (index . 1) "original_operation" "bindings" "mqtt" "qos" }}{{ (index . 1).original_operation.bindings.mqtt.qos }}{{ else }}0{{ end }}
+{{ (index . 1).unique_id }}_RETAIN = {{ if key_exists (index . 1) "original_operation" "bindings" "mqtt" "retain" }}{{ (index . 1).original_operation.bindings.mqtt.retain }}{{ else }}false{{ end }}
+        {{ end }}
+        {{ if eq (index . 1).protocol "amqp" }}
+            {{ if key_exists (index . 1) "channel_bindings" "amqp" "exchange" "name" }}
+{{ (index . 1).unique_id }}_EXCHANGE = "{{ (index . 1).channel_bindings.amqp.exchange.name }}"
+            {{ end }}
+            {{ if key_exists (index . 1) "channel_bindings" "amqp" "queue" "name" }}
+{{ (index . 1).unique_id }}_QUEUE = "{{ (index . 1).channel_bindings.amqp.queue.name }}"
+            {{ end }}
+        {{ end }}
+        {{ if (index . 1).ws_query }}
+{{ (index . 1).unique_id }}_QUERY = "{{ (index . 1).ws_query }}"
+        {{ end }}
+        {{ if (index . 1).ws_headers }}
+{{ (index . 1).unique_id }}_HEADERS = "{{ (index . 1).ws_headers }}"
+        {{ end }}
+#{{ (index . 1).unique_id }}_POLICY_DENIED_SUBJECT = "{{ (index . 0) }}.denied"
 {{ (index . 1).unique_id }}_SUBJECT = "{{ (index . 0) }}"
 {{ end }}
 
 {{ range .publish_channels }}
 ################{{ (index . 1).unique_id }}################
-        {{ if (index . 1).original_operation.bindings }}
-                {{ if (index . 1).original_operation.bindings.nats.queue }}
+        {{ if eq (index . 1).protocol "kafka" }}
+{{ (index . 1).unique_id }}_GROUP_ID = "{{ if (index . 1).kafka_group_id }}{{ (index . 1).kafka_group_id }}{{ else }}{{ (index . 1).unique_id }}{{ end }}"
+            {{ if (index . 1).kafka_client_id }}
+{{ (index . 1).unique_id }}_CLIENT_ID = "{{ (index . 1).kafka_client_id }}"
+            {{ end }}
+        {{ end }}
+        {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "queue" }}
 {{ (index . 1).unique_id}}_QUEUE = "{{ (index . 1).original_operation.bindings.nats.queue}}"
-                {{else}}
+        {{ else if key_exists (index . 1) "original_operation" "bindings" "nats" "streamname" }}
 {{ (index . 1).unique_id}}_STREAM = "{{ (index . 1).original_operation.bindings.nats.streamname}}"
-                {{ end }}
+{{ (index . 1).unique_id }}_CONSUMER = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "name" }}{{ (index . 1).original_operation.bindings.nats.consumer.name }}{{ else }}{{ (index . 1).unique_id }}{{ end }}"
+{{ (index . 1).unique_id }}_CONSUMER_MODE = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "mode" }}{{ (index . 1).original_operation.bindings.nats.consumer.mode }}{{ else }}pull{{ end }}"
+{{ (index . 1).unique_id }}_ACK_POLICY = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "ackPolicy" }}{{ (index . 1).original_operation.bindings.nats.consumer.ackPolicy }}{{ else }}explicit{{ end }}"
+{{ (index . 1).unique_id }}_DELIVER_POLICY = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "deliverPolicy" }}{{ (index . 1).original_operation.bindings.nats.consumer.deliverPolicy }}{{ else }}all{{ end }}"
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "optStartTime" }}
+{{ (index . 1).unique_id }}_START_TIME = "{{ (index . 1).original_operation.bindings.nats.consumer.optStartTime }}"
+            {{ end }}
+{{ (index . 1).unique_id }}_MAX_DELIVER = {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "maxDeliver" }}{{ (index . 1).original_operation.bindings.nats.consumer.maxDeliver }}{{ else }}-1{{ end }}
+{{ (index . 1).unique_id }}_ACK_WAIT_MS = {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "ackWait" }}{{ (index . 1).original_operation.bindings.nats.consumer.ackWait }}{{ else }}30000{{ end }}
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "filterSubjects" }}
+{{ (index . 1).unique_id }}_FILTER_SUBJECTS = "{{ range $i, $subject := (index . 1).original_operation.bindings.nats.consumer.filterSubjects }}{{ if $i }},{{ end }}{{ $subject }}{{ end }}"
+            {{ end }}
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "maxBatch" }}
+{{ (index . 1).unique_id }}_PULL_BATCH = {{ (index . 1).original_operation.bindings.nats.consumer.maxBatch }}
+            {{ end }}
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "consumer" "expires" }}
+{{ (index . 1).unique_id }}_PULL_EXPIRES_MS = {{ (index . 1).original_operation.bindings.nats.consumer.expires }}
+            {{ end }}
+{{ (index . 1).unique_id }}_STREAM_RETENTION = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "stream" "retention" }}{{ (index . 1).original_operation.bindings.nats.stream.retention }}{{ else }}limits{{ end }}"
+{{ (index . 1).unique_id }}_STREAM_STORAGE = "{{ if key_exists (index . 1) "original_operation" "bindings" "nats" "stream" "storage" }}{{ (index . 1).original_operation.bindings.nats.stream.storage }}{{ else }}file{{ end }}"
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "stream" "maxAge" }}
+{{ (index . 1).unique_id }}_STREAM_MAX_AGE_MS = {{ (index . 1).original_operation.bindings.nats.stream.maxAge }}
+            {{ end }}
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "stream" "maxMsgs" }}
+{{ (index . 1).unique_id }}_STREAM_MAX_MSGS = {{ (index . 1).original_operation.bindings.nats.stream.maxMsgs }}
+            {{ end }}
+            {{ if key_exists (index . 1) "original_operation" "bindings" "nats" "stream" "maxBytes" }}
+{{ (index . 1).unique_id }}_STREAM_MAX_BYTES = {{ (index . 1).original_operation.bindings.nats.stream.maxBytes }}
+            {{ end }}
+        {{ end }}
+        {{ if eq (index . 1).protocol "mqtt" }}
+{{ (index . 1).unique_id }}_QOS = {{ if key_exists (index . 1) "original_operation" "bindings" "mqtt" "qos" }}{{ (index . 1).original_operation.bindings.mqtt.qos }}{{ else }}0{{ end }}
+{{ (index . 1).unique_id }}_RETAIN = {{ if key_exists (index . 1) "original_operation" "bindings" "mqtt" "retain" }}{{ (index . 1).original_operation.bindings.mqtt.retain }}{{ else }}false{{ end }}
+        {{ end }}
+        {{ if eq (index . 1).protocol "amqp" }}
+            {{ if key_exists (index . 1) "channel_bindings" "amqp" "exchange" "name" }}
+{{ (index . 1).unique_id }}_EXCHANGE = "{{ (index . 1).channel_bindings.amqp.exchange.name }}"
+            {{ end }}
+            {{ if key_exists (index . 1) "channel_bindings" "amqp" "queue" "name" }}
+{{ (index . 1).unique_id }}_QUEUE = "{{ (index . 1).channel_bindings.amqp.queue.name }}"
+            {{ end }}
+        {{ end }}
+        {{ if and (eq (index . 1).protocol "redis") (index . 1).redis_stream }}
+{{ (index . 1).unique_id }}_GROUP = "{{ if key_exists (index . 1) "original_operation" "bindings" "redis" "consumerGroup" }}{{ (index . 1).original_operation.bindings.redis.consumerGroup }}{{ else }}{{ (index . 1).unique_id }}{{ end }}"
+            {{ if key_exists (index . 1) "original_operation" "bindings" "redis" "consumerName" }}
+{{ (index . 1).unique_id }}_CONSUMER = "{{ (index . 1).original_operation.bindings.redis.consumerName }}"
+            {{ end }}
+{{ (index . 1).unique_id }}_MIN_IDLE_MS = {{ if key_exists (index . 1) "original_operation" "bindings" "redis" "minIdleTime" }}{{ (index . 1).original_operation.bindings.redis.minIdleTime }}{{ else }}60000{{ end }}
+        {{ end }}
+        {{ if (index . 1).ws_query }}
+{{ (index . 1).unique_id }}_QUERY = "{{ (index . 1).ws_query }}"
+        {{ end }}
+        {{ if (index . 1).ws_headers }}
+{{ (index . 1).unique_id }}_HEADERS = "{{ (index . 1).ws_headers }}"
+        {{ end }}
+{{ (index . 1).unique_id }}_MAX_IN_FLIGHT = {{ if eq (index . 1).protocol "kafka" }}1{{ else }}10{{ end }}
+{{ (index . 1).unique_id }}_MAX_ATTEMPTS = {{ if key_exists (index . 1) "original_operation" "x-retry" "maxAttempts" }}{{ index (index (index . 1).original_operation "x-retry") "maxAttempts" }}{{ else }}3{{ end }}
+{{ (index . 1).unique_id }}_RETRY_BACKOFF_MS = {{ if key_exists (index . 1) "original_operation" "x-retry" "backoff" }}{{ index (index (index . 1).original_operation "x-retry") "backoff" }}{{ else }}100{{ end }}
+{{ (index . 1).unique_id }}_RETRY_MAX_BACKOFF_MS = {{ if key_exists (index . 1) "original_operation" "x-retry" "maxBackoff" }}{{ index (index (index . 1).original_operation "x-retry") "maxBackoff" }}{{ else }}10000{{ end }}
+        {{ if key_exists (index . 1) "original_operation" "x-dead-letter-channel" }}
+{{ (index . 1).unique_id }}_DEAD_LETTER_SUBJECT = "{{ index (index . 1).original_operation "x-dead-letter-channel" }}"
+        {{ else }}
+#{{ (index . 1).unique_id }}_DEAD_LETTER_SUBJECT = "{{ (index . 0) }}.dead"
         {{ end }}
+#{{ (index . 1).unique_id }}_POLICY_DENIED_SUBJECT = "{{ (index . 0) }}.denied"
 {{ (index . 1).unique_id }}_SUBJECT = "{{ (index . 0) }}"
 {{ end }}
 
 
 OPA_ENABLED = false
-#OPA_REMOTE_URL = "localhost:4042"
+#OPA_REMOTE_URL = "http://localhost:8181/v1/data/app/allow"
 #OPA_LOCAL_WASM_PATH = "some/path"
+#OPA_WASM_ENTRYPOINT = "app/allow"
@@ -6,23 +6,55 @@ edition = "2021"
 # See more keys and their definitions at https://doc.rust-lang.org/cargo/reference/manifest.html
 
 [dependencies]
-async-nats = "0.29.0"
+{{ if eq .protocol "kafka" }}
+rdkafka = { version = "0.36.2", features = ["ssl"] }
+{{ else if eq .protocol "mqtt" }}
+rumqttc = "0.24.0"
+rustls-pemfile = "2.1.0"
+rustls-native-certs = "0.7.0"
+{{ else if eq .protocol "amqp" }}
+lapin = "2.5.5"
+rustls-pemfile = "2.1.0"
+{{ else if eq .protocol "ws" }}
+tokio-tungstenite = { version = "0.21.0", features = ["native-tls"] }
+{{ else if eq .protocol "mercure" }}
+eventsource-stream = "0.2.3"
+jsonwebtoken = "9.3.0"
+{{ else if eq .protocol "redis" }}
+redis = { version = "0.27.6", features = ["tokio-comp", "tokio-native-tls-comp", "streams"] }
+{{ else }}
+async-nats = "0.33.0"
+# a custom tls config verifying another server name than the host needs the dangerous_configuration of rustls
+rustls = { version = "0.21.6", features = ["dangerous_configuration"] }
+rustls-pemfile = "1.0.2"
+rustls-native-certs = "0.6"
+time = { version = "0.3.24", features = ["parsing"] }
+{{ end }}
 futures = "0.3.28"
 serde = "1.0.164"
 serde_json = "1.0.97"
 tokio = { version = "1.28.2", features = ["full"] }
 dotenv = "0.15.0"
+toml = "0.8"
+serde_yaml = "0.9"
 clap = {version = "4.3.0", features = ["derive"]}
-opentelemetry = { version = "*", features = ["rt-tokio"] }
-opentelemetry-jaeger = { version = "*", features = ["rt-tokio", "isahc_collector_client"] }
+opentelemetry = { version = "0.19.0", features = ["rt-tokio"] }
+opentelemetry-otlp = "0.12.0"
 log = "0.4.0"
 env_logger = "0.10.0"
 anyhow = "1.0.71"
-reqwest = "0.11.18"
+async-trait = "0.1.73"
+reqwest = { version = "0.11.18"{{ if eq .protocol "mercure" }}, features = ["stream"]{{ end }} }
 wasmtime = "9.0.3"
 opa-wasm = { git = "https://github.com/matrix-org/rust-opa-wasm.git" }
-cargo_metadata = "0.15.4"
 warp = "0.3.5"
 lazy_static = "1.4"
 jsonschema = "0.17.0"
+uuid = { version = "1.3.3", features = ["v4"] }
+{{ range .content_types }}
+{{ if eq . "application/msgpack" }}rmp-serde = "1.1.1"{{ end }}
+{{ if eq . "application/cbor" }}ciborium = "0.2.1"{{ end }}
+{{ if eq . "application/vnd.apache.avro" }}apache-avro = "0.15.0"{{ end }}
+{{ if eq . "application/x-protobuf" }}prost = "0.11.9"{{ end }}
+{{ end }}
 
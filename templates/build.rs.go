@@ -0,0 +1,23 @@
+use std::process::Command;
+
+/// passes the git commit the service is built from to the build info endpoint,
+/// a `GIT_COMMIT` variable wins, e.g. in container builds without the `.git` directory
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    if std::path::Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+    }
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+}
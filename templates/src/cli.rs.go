@@ -1,7 +1,5 @@
 use clap::Parser;
 use crate::{model::*, utils::*};
-use async_nats::jetstream::Context;
-use async_nats::{jetstream, Client, Message};
 
 /// specify Messages to send using your new Microservice!
 #[derive(Parser, Debug)]
@@ -20,22 +18,22 @@ pub struct Args {
 
 
 
-pub async fn send_all_messages(client: &Client)-> Result<(), async_nats::Error>{
+pub async fn send_all_messages<B: MessageBroker>(client: &B)-> Result<(), Box<dyn std::error::Error + Send + Sync>>{
 	//TODO: modify this template to iterate over .subscribe channels so that they are sent to their respective channels
     Ok(())
 
 }
 
-pub async fn handle_cli(client: &Client, command: &String, message: &String)-> Result<(), async_nats::Error> {
+pub async fn handle_cli<B: MessageBroker>(client: &B, command: &String, message: &String)-> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     match command.as_str(){
         "all" => {
-            send_all_messages(&client).await?;
+            send_all_messages(client).await?;
         },
         "" => {
                 ();
         },
         _ => {
-            client.publish(command.into(), message.to_owned().into()).await?;
+            client.publish("", command, HeaderMap::new(), message.as_bytes()).await?;
             println!("Sent message {:?} to {}",message, command);
         }
     }
@@ -1,19 +1,734 @@
 use dotenv::dotenv;
 use lazy_static::lazy_static;
-use std::{collections::HashMap, env, sync::RwLock};
+use serde_json::Value;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::{collections::HashMap, env, fmt, str::FromStr, sync::RwLock};
+{{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+use std::path::PathBuf;
+{{ end }}
+{{ if eq .protocol "nats" }}
+use async_nats::jetstream::consumer::{AckPolicy, DeliverPolicy};
+use async_nats::jetstream::stream::{RetentionPolicy, StorageType};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+{{ end }}
 
 
 lazy_static! {
     static ref ENV_VARS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
 }
 
-pub fn initialize_env() {
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// reads the settings from the `.env` file, the file `CONFIG_FILE` points to and the environment,
+/// a setting of the environment wins over one of the config file, which wins over one of the `.env` file
+pub fn initialize_env() -> Result<(), String> {
+    let environment: HashMap<String, String> = env::vars().collect();
+    // the .env file only sets the variables missing in the environment
     dotenv().ok();
-    let env: HashMap<String, String> = env::vars().collect();
-    ENV_VARS.try_write().unwrap().clear();
-    ENV_VARS.try_write().unwrap().extend(env);
+    let mut vars: HashMap<String, String> = env::vars().collect();
+    let config_file = vars.get("CONFIG_FILE").filter(|path| !path.is_empty()).cloned();
+    let result = match config_file {
+        Some(path) => read_config_file(&path).map(|file| vars.extend(file)),
+        None => Ok(()),
+    };
+    vars.extend(environment);
+    let mut env_vars = ENV_VARS.write().unwrap();
+    env_vars.clear();
+    env_vars.extend(vars);
+    result
 }
 
-pub fn get_env(key: &str) -> Option<String> {
+fn get_env(key: &str) -> Option<String> {
     ENV_VARS.read().unwrap().get(key).cloned()
 }
+
+/// the settings of a `.toml`, `.yaml` or `.yml` file, named like the env variables,
+/// the keys of a table are prefixed with its name, e.g. `SUBJECT` of the table `user_signed_up` is `user_signed_up_SUBJECT`
+fn read_config_file(path: &str) -> Result<HashMap<String, String>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read CONFIG_FILE {}: {}", path, e))?;
+    let settings: Value = match path.rsplit('.').next() {
+        Some("toml") => toml::from_str(&content).map_err(|e| format!("Failed to parse CONFIG_FILE {}: {}", path, e))?,
+        Some("yaml") | Some("yml") => {
+            serde_yaml::from_str(&content).map_err(|e| format!("Failed to parse CONFIG_FILE {}: {}", path, e))?
+        }
+        _ => return Err(format!("CONFIG_FILE {} has to be a .toml, .yaml or .yml file", path)),
+    };
+    let mut vars = HashMap::new();
+    match settings {
+        Value::Object(settings) => flatten("", settings, &mut vars),
+        Value::Null => (),
+        _ => return Err(format!("CONFIG_FILE {} has to hold a table of settings", path)),
+    }
+    Ok(vars)
+}
+
+fn flatten(prefix: &str, settings: serde_json::Map<String, Value>, vars: &mut HashMap<String, String>) {
+    for (key, value) in settings {
+        let key = format!("{}{}", prefix, key);
+        match value {
+            Value::Object(table) => flatten(&format!("{}_", key), table, vars),
+            Value::Array(items) => {
+                let items: Vec<String> = items.into_iter().map(setting_value).collect();
+                vars.insert(key, items.join(","));
+            }
+            value => {
+                vars.insert(key, setting_value(value));
+            }
+        }
+    }
+}
+
+fn setting_value(value: Value) -> String {
+    match value {
+        Value::String(value) => value,
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// the settings of the service, loaded and checked once at startup by `load`
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// port of the health, metrics and metadata endpoints
+    pub service_port: u16,
+    /// the broker to connect to, `memory` runs the service on the in-memory broker
+    pub server_url: String,
+    pub log_level: String,
+    /// `pretty` or `json`
+    pub log_format: String,
+    pub tracing_enabled: bool,
+    pub schema_validation_enabled: bool,
+    /// how long a requester waits for the reply
+    pub request_timeout: Duration,
+    /// how long the handled messages get to finish on shutdown
+    pub shutdown_timeout: Duration,
+    /// /healthz fails once a message has been handled for longer, its handler is stuck
+    pub liveness_handler_timeout: Duration,
+    /// whether sensitive payload fields are logged as `[REDACTED]`
+    pub log_redact: bool,
+    /// fields redacted in the logs besides the ones the schemas mark as sensitive
+    pub log_redact_fields: Vec<String>,
+    /// the OTLP collector the spans are exported to
+    pub otlp_endpoint: String,
+    pub opa: OpaConfig,
+    {{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+    pub tls: TlsConfig,
+    {{ end }}
+    {{ if eq .protocol "mqtt" }}
+    pub mqtt: MqttConfig,
+    {{ else if eq .protocol "kafka" }}
+    pub kafka: KafkaConfig,
+    {{ else if eq .protocol "amqp" }}
+    pub amqp: AmqpConfig,
+    {{ else if eq .protocol "ws" }}
+    pub ws: WsConfig,
+    {{ else if eq .protocol "mercure" }}
+    pub mercure: MercureConfig,
+    {{ end }}
+    pub channels: Channels,
+}
+
+/// where the policy the messages are checked against is evaluated
+#[derive(Clone, Debug, Default)]
+pub struct OpaConfig {
+    pub enabled: bool,
+    /// the opa server the input is posted to, preferred over `local_wasm_path`
+    pub remote_url: Option<String>,
+    /// the policy compiled to wasm, evaluated in the service
+    pub local_wasm_path: Option<String>,
+    /// the rule of the wasm policy that is evaluated, as passed to `opa build -e`, required by `local_wasm_path`
+    pub wasm_entrypoint: String,
+}
+{{ if eq .protocol "mqtt" }}
+
+/// the session of the mqtt client, from the mqtt server binding
+#[derive(Clone, Debug)]
+pub struct MqttConfig {
+    /// `MQTT_CLIENT_ID`, the service name with a random suffix by default
+    pub client_id: String,
+    pub keep_alive: Option<Duration>,
+    /// whether the broker discards the session, and the unacknowledged messages, on connect
+    pub clean_session: Option<bool>,
+    /// the message the broker publishes once the connection is lost, `MQTT_LAST_WILL_*`
+    pub last_will: Option<LastWill>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LastWill {
+    pub topic: String,
+    pub message: String,
+    /// 0, 1 or 2
+    pub qos: u8,
+    pub retain: bool,
+}
+{{ else if eq .protocol "kafka" }}
+
+#[derive(Clone, Debug)]
+pub struct KafkaConfig {
+    /// where consumer groups without committed offset start, `earliest` or `latest`
+    pub auto_offset_reset: String,
+}
+{{ else if eq .protocol "amqp" }}
+
+#[derive(Clone, Debug)]
+pub struct AmqpConfig {
+    /// unacked messages a consumer receives at once
+    pub prefetch: u16,
+}
+{{ else if eq .protocol "ws" }}
+
+#[derive(Clone, Debug)]
+pub struct WsConfig {
+    /// `client` connects to the channel paths of SERVER_URL, `server` accepts connections on them
+    pub client_mode: bool,
+    /// the longest wait before a lost client connection is reopened
+    pub max_backoff: Duration,
+}
+{{ else if eq .protocol "mercure" }}
+
+#[derive(Clone, Debug)]
+pub struct MercureConfig {
+    pub publisher: MercureJwt,
+    pub subscriber: MercureJwt,
+    /// wait before resuming a closed event stream, unless the hub sends a retry
+    pub retry: Duration,
+}
+
+/// the token of a role, `MERCURE_{role}_JWT`, or the key to sign one with (HS256), `MERCURE_{role}_JWT_KEY`
+#[derive(Clone, Debug)]
+pub struct MercureJwt {
+    pub jwt: Option<String>,
+    pub jwt_key: Option<String>,
+}
+{{ end }}
+
+/// the settings of a channel, `{channel}_SUBJECT`, `{channel}_QUEUE`, `{channel}_STREAM`, `{channel}_POLICY_DENIED_SUBJECT`
+/// and the other `{channel}_*` settings
+#[derive(Clone, Debug)]
+pub struct ChannelConfig {
+    pub subject: String,
+    pub queue: Option<String>,
+    pub stream: Option<String>,
+    /// where messages denied by the policy are republished, they are dropped if it is not set
+    pub policy_denied_subject: Option<String>,
+    pub retry: RetryPolicy,
+    /// messages handled at the same time, `{channel}_MAX_IN_FLIGHT` (1)
+    pub max_in_flight: usize,
+    {{ if eq .protocol "nats" }}
+    /// the stream and consumer of stream channels
+    pub jetstream: JetStreamConfig,
+    {{ else if eq .protocol "mqtt" }}
+    /// 0, 1 or 2, `{channel}_QOS`
+    pub qos: u8,
+    pub retain: bool,
+    {{ else if eq .protocol "kafka" }}
+    /// the consumer group, `{channel}_GROUP_ID`, the channel name by default
+    pub group_id: String,
+    pub client_id: Option<String>,
+    {{ else if eq .protocol "redis" }}
+    /// the consumer group of stream channels, `{channel}_GROUP`, the channel name by default
+    pub group: String,
+    /// `{channel}_CONSUMER`, `HOSTNAME` or the service name with a random suffix by default
+    pub consumer: String,
+    /// how long an entry stays pending before it is claimed again, `{channel}_MIN_IDLE_MS` (60000)
+    pub min_idle_time: Duration,
+    {{ else if eq .protocol "ws" }}
+    /// the percent-encoded query and handshake headers (`name=value` pairs) of client connections
+    pub query: Option<String>,
+    pub headers: Option<String>,
+    {{ else if eq .protocol "amqp" }}
+    pub exchange: Option<String>,
+    {{ end }}
+}
+
+/// how often the handler of a channel is tried and where messages go that failed every attempt,
+/// `{channel}_MAX_ATTEMPTS` (3), `{channel}_RETRY_BACKOFF_MS` (100), `{channel}_RETRY_MAX_BACKOFF_MS` (10000)
+/// and `{channel}_DEAD_LETTER_SUBJECT`
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// the delay before the second attempt, doubled after every further attempt
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// the subject failed messages are republished on, with the failure in `x-dead-letter-*` headers
+    pub dead_letter_subject: Option<String>,
+}
+
+/// 3 attempts with 100 ms backoff up to 10 s, no dead letter subject
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(10000),
+            dead_letter_subject: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// the delay after the failed `attempt`, starting at 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff)
+    }
+}
+{{ if eq .protocol "nats" }}
+
+/// the jetstream stream a stream channel reads, created if missing, and its durable consumer, created or updated
+#[derive(Clone, Debug)]
+pub struct JetStreamConfig {
+    /// `{channel}_CONSUMER`, the channel name by default
+    pub consumer: String,
+    /// whether the consumer pushes its messages to an inbox instead of being pulled, `{channel}_CONSUMER_MODE`
+    pub push: bool,
+    pub deliver_policy: DeliverPolicy,
+    pub ack_policy: AckPolicy,
+    pub ack_wait: Duration,
+    /// -1 redelivers without limit
+    pub max_deliver: i64,
+    pub filter_subjects: Vec<String>,
+    pub pull_batch: Option<usize>,
+    pub pull_expires: Option<Duration>,
+    pub retention: RetentionPolicy,
+    pub storage: StorageType,
+    /// zero means no limit, like for the other stream limits
+    pub max_age: Duration,
+    pub max_messages: i64,
+    pub max_bytes: i64,
+}
+{{ end }}
+
+/// the settings of every channel of the spec
+#[derive(Clone, Debug)]
+pub struct Channels {
+    {{ range .subscribe_channels }}
+    pub {{ (index . 1).unique_id }}: ChannelConfig,
+    {{ end }}
+    {{ range .publish_channels }}
+    pub {{ (index . 1).unique_id }}: ChannelConfig,
+    {{ end }}
+}
+
+impl Channels {
+    /// the settings of the channel with the id `channel`
+    pub fn get(&self, channel: &str) -> Option<&ChannelConfig> {
+        match channel {
+            {{ range .subscribe_channels }}
+            "{{ (index . 1).unique_id }}" => Some(&self.{{ (index . 1).unique_id }}),
+            {{ end }}
+            {{ range .publish_channels }}
+            "{{ (index . 1).unique_id }}" => Some(&self.{{ (index . 1).unique_id }}),
+            {{ end }}
+            _ => None,
+        }
+    }
+}
+
+/// every setting that is missing or malformed
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// shows the problems like `Display`, as `main` prints a returned error with `Debug`
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// reads the settings like `initialize_env` and checks them, the error lists every missing or malformed setting,
+/// later calls return the config loaded first
+pub fn load() -> Result<&'static Config, ConfigError> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let mut settings = Settings::default();
+    if let Err(e) = initialize_env() {
+        settings.problems.push(e);
+    }
+    let opa = OpaConfig {
+        enabled: settings.parse("OPA_ENABLED", false, "true or false"),
+        remote_url: settings.optional("OPA_REMOTE_URL"),
+        local_wasm_path: settings.optional("OPA_LOCAL_WASM_PATH"),
+        wasm_entrypoint: settings.optional("OPA_WASM_ENTRYPOINT").unwrap_or_default(),
+    };
+    if opa.local_wasm_path.is_some() && opa.wasm_entrypoint.is_empty() {
+        settings
+            .problems
+            .push("OPA_LOCAL_WASM_PATH needs OPA_WASM_ENTRYPOINT, the rule to evaluate".to_string());
+    }
+    let config = Config {
+        service_port: settings.parse_required("SERVICE_PORT", "a port number"),
+        server_url: settings.required("SERVER_URL"),
+        log_level: settings.one_of("LOG_LEVEL", "INFO", &["OFF", "ERROR", "WARN", "INFO", "DEBUG", "TRACE"]),
+        log_format: settings.one_of("LOG_FORMAT", "pretty", &["pretty", "json"]),
+        tracing_enabled: settings.parse("TRACING_ENABLED", false, "true or false"),
+        schema_validation_enabled: settings.parse("SCHEMA_VALIDATION_ENABLED", false, "true or false"),
+        request_timeout: settings.millis("REQUEST_TIMEOUT_MS", Duration::from_secs(5)),
+        shutdown_timeout: settings.millis("SHUTDOWN_TIMEOUT_MS", Duration::from_secs(20)),
+        liveness_handler_timeout: settings.millis("LIVENESS_HANDLER_TIMEOUT_MS", Duration::from_secs(300)),
+        log_redact: settings.parse("LOG_REDACT", true, "true or false"),
+        log_redact_fields: settings.list("LOG_REDACT_FIELDS"),
+        otlp_endpoint: settings
+            .optional("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .unwrap_or_else(|| "http://localhost:4317".to_string()),
+        opa,
+        {{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+        tls: settings.tls(),
+        {{ end }}
+        {{ if eq .protocol "mqtt" }}
+        mqtt: MqttConfig {
+            client_id: settings
+                .optional("MQTT_CLIENT_ID")
+                .unwrap_or_else(|| format!("{{ to_lower (replace .title " " "_") }}-{}", uuid::Uuid::new_v4())),
+            keep_alive: settings
+                .parse_optional("MQTT_KEEP_ALIVE", "a number of seconds")
+                .map(Duration::from_secs),
+            clean_session: settings.parse_optional("MQTT_CLEAN_SESSION", "true or false"),
+            last_will: settings.optional("MQTT_LAST_WILL_TOPIC").map(|topic| LastWill {
+                topic,
+                message: settings.optional("MQTT_LAST_WILL_MESSAGE").unwrap_or_default(),
+                qos: settings.qos("MQTT_LAST_WILL_QOS"),
+                retain: settings.parse("MQTT_LAST_WILL_RETAIN", false, "true or false"),
+            }),
+        },
+        {{ else if eq .protocol "kafka" }}
+        kafka: KafkaConfig {
+            auto_offset_reset: settings.one_of("KAFKA_AUTO_OFFSET_RESET", "earliest", &["earliest", "latest"]),
+        },
+        {{ else if eq .protocol "amqp" }}
+        amqp: AmqpConfig {
+            prefetch: settings.parse("AMQP_PREFETCH", 10, "a number of messages"),
+        },
+        {{ else if eq .protocol "ws" }}
+        ws: WsConfig {
+            client_mode: settings.one_of("WS_MODE", "server", &["server", "client"]) == "client",
+            max_backoff: settings.millis("WS_MAX_BACKOFF_MS", Duration::from_secs(30)),
+        },
+        {{ else if eq .protocol "mercure" }}
+        mercure: MercureConfig {
+            publisher: settings.mercure_jwt("PUBLISHER"),
+            subscriber: settings.mercure_jwt("SUBSCRIBER"),
+            retry: settings.millis("MERCURE_RETRY_MS", Duration::from_secs(3)),
+        },
+        {{ end }}
+        channels: Channels {
+            {{ range .subscribe_channels }}
+            {{ (index . 1).unique_id }}: settings.channel("{{ (index . 1).unique_id }}"),
+            {{ end }}
+            {{ range .publish_channels }}
+            {{ (index . 1).unique_id }}: settings.channel("{{ (index . 1).unique_id }}"),
+            {{ end }}
+        },
+    };
+    if config.opa.enabled && config.opa.remote_url.is_none() && config.opa.local_wasm_path.is_none() {
+        settings
+            .problems
+            .push("OPA_ENABLED needs OPA_REMOTE_URL or OPA_LOCAL_WASM_PATH".to_string());
+    }
+    if !settings.problems.is_empty() {
+        return Err(ConfigError(settings.problems));
+    }
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// the config loaded at startup
+///
+/// # Panics
+/// if `load` did not succeed before
+pub fn config() -> &'static Config {
+    CONFIG.get().expect("The config is used before it was loaded")
+}
+
+/// reads the settings and collects the problems instead of stopping at the first one
+#[derive(Default)]
+struct Settings {
+    problems: Vec<String>,
+}
+
+impl Settings {
+    /// the setting `key`, empty values count as not set
+    fn optional(&self, key: &str) -> Option<String> {
+        get_env(key).filter(|value| !value.is_empty())
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        match self.optional(key) {
+            Some(value) => value,
+            None => {
+                self.problems.push(format!("{} is not set", key));
+                String::new()
+            }
+        }
+    }
+
+    /// the setting `key` parsed, `default` if it is not set
+    fn parse<T: FromStr>(&mut self, key: &str, default: T, expected: &str) -> T {
+        match self.optional(key) {
+            Some(value) => match value.parse() {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    self.problems.push(format!("{} must be {}, not {}", key, expected, value));
+                    default
+                }
+            },
+            None => default,
+        }
+    }
+
+    {{ if or (eq .protocol "nats") (eq .protocol "mqtt") }}
+    /// the setting `key` parsed, `None` if it is not set
+    fn parse_optional<T: FromStr>(&mut self, key: &str, expected: &str) -> Option<T> {
+        let value = self.optional(key)?;
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.problems.push(format!("{} must be {}, not {}", key, expected, value));
+                None
+            }
+        }
+    }
+    {{ end }}
+
+    /// the milliseconds of the setting `key`, `default` if it is not set
+    fn millis(&mut self, key: &str, default: Duration) -> Duration {
+        Duration::from_millis(self.parse(key, default.as_millis() as u64, "a number of milliseconds"))
+    }
+
+    /// the comma separated values of the setting `key`
+    fn list(&self, key: &str) -> Vec<String> {
+        self.optional(key)
+            .map(|values| {
+                values
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn parse_required<T: FromStr + Default>(&mut self, key: &str, expected: &str) -> T {
+        if self.optional(key).is_none() {
+            self.problems.push(format!("{} is not set", key));
+            return T::default();
+        }
+        self.parse(key, T::default(), expected)
+    }
+
+    /// the setting `key` if it is one of `allowed`, `default` if it is not set
+    fn one_of(&mut self, key: &str, default: &str, allowed: &[&str]) -> String {
+        match self.optional(key) {
+            Some(value) if allowed.contains(&value.as_str()) => value,
+            Some(value) => {
+                self.problems.push(format!("{} must be one of {}, not {}", key, allowed.join(", "), value));
+                default.to_string()
+            }
+            None => default.to_string(),
+        }
+    }
+
+    {{ if or (eq .protocol "nats") (eq .protocol "mqtt") }}
+    /// the value of the setting `key` named like one of `choices`, `default` if it is not set
+    fn choice<T: Clone>(&mut self, key: &str, default: T, choices: &[(&str, T)]) -> T {
+        let value = match self.optional(key) {
+            Some(value) => value,
+            None => return default,
+        };
+        match choices.iter().find(|(name, _)| *name == value) {
+            Some((_, choice)) => choice.clone(),
+            None => {
+                let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+                self.problems.push(format!("{} must be one of {}, not {}", key, names.join(", "), value));
+                default
+            }
+        }
+    }
+    {{ end }}
+
+    fn channel(&mut self, channel: &str) -> ChannelConfig {
+        let setting = |name: &str| format!("{}_{}", channel, name);
+        let retry = RetryPolicy::default();
+        ChannelConfig {
+            subject: self.required(&setting("SUBJECT")),
+            queue: self.optional(&setting("QUEUE")),
+            stream: self.optional(&setting("STREAM")),
+            policy_denied_subject: self.optional(&setting("POLICY_DENIED_SUBJECT")),
+            retry: RetryPolicy {
+                max_attempts: self.parse(&setting("MAX_ATTEMPTS"), retry.max_attempts, "a number of attempts").max(1),
+                backoff: self.millis(&setting("RETRY_BACKOFF_MS"), retry.backoff),
+                max_backoff: self.millis(&setting("RETRY_MAX_BACKOFF_MS"), retry.max_backoff),
+                dead_letter_subject: self.optional(&setting("DEAD_LETTER_SUBJECT")),
+            },
+            max_in_flight: self.parse::<usize>(&setting("MAX_IN_FLIGHT"), 1, "a number of messages").max(1),
+            {{ if eq .protocol "nats" }}
+            jetstream: self.jetstream(channel),
+            {{ else if eq .protocol "mqtt" }}
+            qos: self.qos(&setting("QOS")),
+            retain: self.parse(&setting("RETAIN"), false, "true or false"),
+            {{ else if eq .protocol "kafka" }}
+            group_id: self.optional(&setting("GROUP_ID")).unwrap_or_else(|| channel.to_string()),
+            client_id: self.optional(&setting("CLIENT_ID")),
+            {{ else if eq .protocol "redis" }}
+            group: self.optional(&setting("GROUP")).unwrap_or_else(|| channel.to_string()),
+            consumer: self
+                .optional(&setting("CONSUMER"))
+                .or_else(|| self.optional("HOSTNAME"))
+                .unwrap_or_else(|| format!("{{ to_lower (replace .title " " "_") }}-{}", uuid::Uuid::new_v4())),
+            min_idle_time: self.millis(&setting("MIN_IDLE_MS"), Duration::from_secs(60)),
+            {{ else if eq .protocol "ws" }}
+            query: self.optional(&setting("QUERY")),
+            headers: self.optional(&setting("HEADERS")),
+            {{ else if eq .protocol "amqp" }}
+            exchange: self.optional(&setting("EXCHANGE")),
+            {{ end }}
+        }
+    }
+    {{ if eq .protocol "nats" }}
+
+    /// the `{channel}_STREAM_*` settings of the stream and the consumer settings of `channel`
+    fn jetstream(&mut self, channel: &str) -> JetStreamConfig {
+        let setting = |name: &str| format!("{}_{}", channel, name);
+        let deliver_policy = self.choice(
+            &setting("DELIVER_POLICY"),
+            Some(DeliverPolicy::All),
+            &[
+                ("all", Some(DeliverPolicy::All)),
+                ("last", Some(DeliverPolicy::Last)),
+                ("new", Some(DeliverPolicy::New)),
+                ("last_per_subject", Some(DeliverPolicy::LastPerSubject)),
+                ("by_start_time", None),
+            ],
+        );
+        // the start time is only read by the by_start_time deliver policy
+        let deliver_policy = match deliver_policy {
+            Some(deliver_policy) => deliver_policy,
+            None => match self.optional(&setting("START_TIME")) {
+                Some(start_time) => match OffsetDateTime::parse(&start_time, &Rfc3339) {
+                    Ok(start_time) => DeliverPolicy::ByStartTime { start_time },
+                    Err(e) => {
+                        self.problems.push(format!("{} must be an RFC 3339 time, not {}: {}", setting("START_TIME"), start_time, e));
+                        DeliverPolicy::All
+                    }
+                },
+                None => {
+                    self.problems.push(format!("{} is required by the by_start_time deliver policy", setting("START_TIME")));
+                    DeliverPolicy::All
+                }
+            },
+        };
+        JetStreamConfig {
+            consumer: self.optional(&setting("CONSUMER")).unwrap_or_else(|| channel.to_string()),
+            push: self.choice(&setting("CONSUMER_MODE"), false, &[("pull", false), ("push", true)]),
+            deliver_policy,
+            ack_policy: self.choice(
+                &setting("ACK_POLICY"),
+                AckPolicy::Explicit,
+                &[("explicit", AckPolicy::Explicit), ("all", AckPolicy::All), ("none", AckPolicy::None)],
+            ),
+            ack_wait: self.millis(&setting("ACK_WAIT_MS"), Duration::ZERO),
+            max_deliver: self.parse(&setting("MAX_DELIVER"), 0, "a number of deliveries"),
+            filter_subjects: self.list(&setting("FILTER_SUBJECTS")),
+            pull_batch: self.parse_optional(&setting("PULL_BATCH"), "a number of messages"),
+            pull_expires: self
+                .parse_optional(&setting("PULL_EXPIRES_MS"), "a number of milliseconds")
+                .map(Duration::from_millis),
+            retention: self.choice(
+                &setting("STREAM_RETENTION"),
+                RetentionPolicy::Limits,
+                &[
+                    ("limits", RetentionPolicy::Limits),
+                    ("interest", RetentionPolicy::Interest),
+                    ("workqueue", RetentionPolicy::WorkQueue),
+                ],
+            ),
+            storage: self.choice(
+                &setting("STREAM_STORAGE"),
+                StorageType::File,
+                &[("file", StorageType::File), ("memory", StorageType::Memory)],
+            ),
+            max_age: self.millis(&setting("STREAM_MAX_AGE_MS"), Duration::ZERO),
+            max_messages: self.parse(&setting("STREAM_MAX_MSGS"), 0, "a number of messages"),
+            max_bytes: self.parse(&setting("STREAM_MAX_BYTES"), 0, "a number of bytes"),
+        }
+    }
+    {{ else if eq .protocol "mqtt" }}
+
+    /// the quality of service level `0`, `1` or `2` of the setting `key`, `0` if it is not set
+    fn qos(&mut self, key: &str) -> u8 {
+        self.choice(key, 0, &[("0", 0), ("1", 1), ("2", 2)])
+    }
+    {{ else if eq .protocol "mercure" }}
+
+    fn mercure_jwt(&self, role: &str) -> MercureJwt {
+        MercureJwt {
+            jwt: self.optional(&format!("MERCURE_{}_JWT", role)),
+            jwt_key: self.optional(&format!("MERCURE_{}_JWT_KEY", role)),
+        }
+    }
+    {{ end }}
+    {{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+
+    /// `TLS_REQUIRED`, `TLS_CA_FILE`, `TLS_CERT_FILE`, `TLS_KEY_FILE` and `TLS_SERVER_NAME`
+    fn tls(&mut self) -> TlsConfig {
+        let client_cert = match (self.optional("TLS_CERT_FILE"), self.optional("TLS_KEY_FILE")) {
+            (Some(cert), Some(key)) => Some((PathBuf::from(cert), PathBuf::from(key))),
+            (None, None) => None,
+            _ => {
+                self.problems
+                    .push("TLS_CERT_FILE and TLS_KEY_FILE have to be set together".to_string());
+                None
+            }
+        };
+        TlsConfig {
+            required: self.parse("TLS_REQUIRED", false, "true or false"),
+            ca_file: self.optional("TLS_CA_FILE").map(PathBuf::from),
+            client_cert,
+            server_name: self.optional("TLS_SERVER_NAME"),
+        }
+    }
+    {{ end }}
+}
+
+/// a secret from the env variable `key` or, if that is not set, read from the file `{key}_FILE` points to (e.g. a docker or kubernetes secret)
+/// empty values count as not set
+pub fn get_secret(key: &str) -> Result<Option<String>, String> {
+    if let Some(value) = get_env(key).filter(|value| !value.is_empty()) {
+        return Ok(Some(value));
+    }
+    match get_env(&format!("{}_FILE", key)).filter(|path| !path.is_empty()) {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(value) => Ok(Some(value.trim_end().to_string()).filter(|value| !value.is_empty())),
+            Err(e) => Err(format!("Failed to read {}_FILE {}: {}", key, path, e)),
+        },
+        None => Ok(None),
+    }
+}
+{{ if or (eq .protocol "nats") (eq .protocol "kafka") (eq .protocol "mqtt") (eq .protocol "amqp") }}
+
+/// tls settings of the broker connection
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// connect with tls even if the scheme of SERVER_URL does not ask for it, and refuse connections without
+    pub required: bool,
+    /// pem file with the certificates of the CAs the server certificate is verified against instead of the system ones, e.g. a self-signed CA
+    pub ca_file: Option<PathBuf>,
+    /// pem files of the client certificate and its private key for mutual tls
+    pub client_cert: Option<(PathBuf, PathBuf)>,
+    /// the name the server certificate is verified against instead of the host of SERVER_URL
+    pub server_name: Option<String>,
+}
+{{ end }}
@@ -1,12 +1,12 @@
-use async_nats::{Client, Message, jetstream};
-use async_nats::jetstream::Context;
-use crate::{publish_message,stream_publish_message,model::*,config::*};
-use std::time;
+use crate::{model::*,config::*,policy::policy::*,utils::*};
+use anyhow::anyhow;
+use std::{time, path::Path};
+use crate::tracing::inject_context;
 use opentelemetry::global;
-use opentelemetry::trace::Tracer;
-use log::{debug, warn};
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::Context;
+use log::{debug, warn, error};
 
-    {{ $isStream := false }}
     {{ $channel := . }}
 
     /// Publish a message in the {{ .unique_id }} channel
@@ -14,49 +14,101 @@ use log::{debug, warn};
     /// {{ range .messages }}
     ///     {{ .unique_id }}
     /// {{ end }}
-    {{ if key_exists . "original_operation" "bindings" "nats" "streamname" }}
-        {{ $isStream := .original_operation.bindings.nats.streamname }}
+    {{ range .messages }}
+    {{ if eq $channel.protocol "kafka" }}
+    /// messages without `key` use the key of the kafka message binding, if there is one
     {{ end }}
-            
-    {{ if $isStream }}
-        {{ range .messages }}
-            pub async fn stream_producer_{{ .unique_id }}(context_stream: &Context, payload : {{ if .payload}} {{ .payload.struct_reference }} {{ else }} () {{ end }}) { //context instead of client
-    let tracer = global::tracer("{{ .unique_id }}_stream_producer");
-        let _span = tracer.start("stream_producer_{{ .unique_id }}");
-        let subject = get_env("{{ $channel.unique_id }}_SUBJECT").unwrap().clone();
+    pub async fn producer_{{ .unique_id }}<B: MessageBroker>(client: &B, payload: {{ if .payload }} {{.payload.struct_reference}} {{else}} () {{end}}{{ if eq $channel.protocol "kafka" }}, key: Option<&str>{{ end }}) {
+    let tracer = global::tracer("{{ .unique_id }}_producer");
+    // the span continues the trace of the message handled when the producer is called, consumers continue it from the headers
+    let context = Context::current_with_span(tracer.start("producer_{{ .unique_id }}"));
+    let mut headers = HeaderMap::new();
+    inject_context(&context, &mut headers);
+    let subject = config().channels.{{ $channel.unique_id }}.subject.clone();
+    // the policy decides on the message before it is encoded, denied messages are not published on the channel
+    let decision = check_policy("{{ $channel.unique_id }}", Action::Send, &subject, Some(&headers), &payload).await;
                 {{ if .payload }}
-                    let payload = match serde_json::to_string(&payload) {
+                    let payload = match {{ if eq .schema_format "protobuf" }}encode_protobuf_payload::<{{ .payload.struct_reference }}>(&payload){{ else }}encode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), &payload){{ end }} {
                         Ok(payload) => payload,
-                        Err(_) => {
-                            warn!("Failed to serialize message payload: {{ .payload.struct_reference }}");
+                        Err(e) => {
+                            warn!("Failed to serialize message payload: {{ .payload.struct_reference }}\nError: {}", e);
                             return;
                         }
                     };
-                    stream_publish_message(context_stream, &subject, &payload).await;
-                {{else}}
-                stream_publish_message(context_stream, &subject, &"").await;
-                {{end}}
-            }
-        {{end}}
-    {{ else }}
-    {{ range .messages }}
-    pub async fn producer_{{ .unique_id }}(client: &Client, payload: {{ if .payload }} {{.payload.struct_reference}} {{else}} () {{end}}) {
-    let tracer = global::tracer("{{ .unique_id }}_producer");
-    let _span = tracer.start("producer_{{ .unique_id }}");
-    let subject = get_env("{{ $channel.unique_id }}_SUBJECT").unwrap().clone();
-                {{ if .payload }}
-                    let payload = match serde_json::to_string(&payload) {
-                        Ok(payload) => payload,
-                        Err(_) => {
-                            warn!("Failed to serialize message payload: {{ .payload.struct_reference }}");
+                {{ else }}
+                    let payload: Vec<u8> = Vec::new();
+                {{ end }}
+                    match decision {
+                        Ok(Decision::Allow) => (),
+                        Ok(Decision::Deny(decision)) => {
+                            if let Err(e) = route_denied(client, "{{ $channel.unique_id }}", &subject, headers, &payload, &decision).await {
+                                error!("Failed to route denied {{ .unique_id }}: {}", e);
+                            }
                             return;
                         }
-                    };
-                    publish_message(client, &subject, &payload).await;
-                {{else}}
-                    publish_message(client, &subject, &"").await;
-                {{end}}
+                        Err(e) => {
+                            error!("Failed to evaluate policy for {{ .unique_id }}: {}", e);
+                            return;
+                        }
+                    }
+                    let published = {{ if eq $channel.protocol "kafka" }}client.publish_keyed("{{ $channel.unique_id }}", &subject, {{ if .kafka_key }}key.or(Some("{{ .kafka_key }}")){{ else }}key{{ end }}, headers, &payload){{ else }}client.publish("{{ $channel.unique_id }}", &subject, headers, &payload){{ end }}.await;
+                    match published {
+                        Ok(()) => count("{{ $channel.unique_id }}", Counter::Published),
+                        Err(e) => error!("Failed to publish {{ .unique_id }} to {}: {}", subject, e),
+                    }
             }
-        {{ end }}
-    {{ end }}
 
+            {{ if $channel.reply }}
+                {{ $request := . }}
+                {{ $reply := index $channel.reply.messages 0 }}
+                /// Send a request in the {{ $channel.unique_id }} channel and wait for the reply on the {{ $channel.reply.unique_id }} channel
+                pub async fn request_{{ .unique_id }}<B: MessageBroker>(client: &B, payload: {{ if .payload }} {{.payload.struct_reference}} {{else}} () {{end}}) -> anyhow::Result<{{ if $reply.payload }} {{ $reply.payload.struct_reference }} {{ else }} () {{ end }}> {
+                    let tracer = global::tracer("{{ .unique_id }}_requester");
+                    let context = Context::current_with_span(tracer.start("request_{{ .unique_id }}"));
+                    let subject = config().channels.{{ $channel.unique_id }}.subject.clone();
+                    let mut payload = serde_json::to_value(&payload)?;
+                    let mut headers = HeaderMap::new();
+                    inject_context(&context, &mut headers);
+                    {{ if .correlation_id_location }}
+                        // reuse a correlation id that is part of the payload, otherwise create a new one
+                        let correlation_id = match extract_correlation_id("{{ .correlation_id_location }}", None, &payload) {
+                            Some(correlation_id) => correlation_id,
+                            None => {
+                                let correlation_id = new_correlation_id();
+                                insert_correlation_id("{{ .correlation_id_location }}", &mut headers, &mut payload, &correlation_id).map_err(|e| anyhow!(e))?;
+                                correlation_id
+                            }
+                        };
+                    {{ end }}
+                    let decision = check_policy("{{ $channel.unique_id }}", Action::Send, &subject, Some(&headers), &payload).await?;
+                    let payload = {{ if eq .schema_format "protobuf" }}encode_protobuf_payload::<{{ .payload.struct_reference }}>(&payload){{ else }}encode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), &payload){{ end }}.map_err(|e| anyhow!(e))?;
+                    if let Decision::Deny(decision) = decision {
+                        route_denied(client, "{{ $channel.unique_id }}", &subject, headers, &payload, &decision).await?;
+                        return Err(anyhow!("Request {{ .unique_id }} denied by policy: {}", decision));
+                    }
+                    let reply = client.request(&subject, headers, &payload).await?;
+                    let reply_payload = {{ if eq $reply.schema_format "protobuf" }}decode_protobuf_payload::<{{ $reply.payload.struct_reference }}>(&reply.payload){{ else }}decode_payload("{{ $reply.content_type }}", Path::new("./src/schemas/{{ $reply.unique_id }}_payload_schema.json"), &reply.payload){{ end }}.map_err(|e| anyhow!(e))?;
+                    {{ if .correlation_id_location }}
+                        let reply_correlation_id = extract_correlation_id(
+                            "{{ if $reply.correlation_id_location }}{{ $reply.correlation_id_location }}{{ else }}{{ $request.correlation_id_location }}{{ end }}",
+                            reply.headers.as_ref(),
+                            &reply_payload,
+                        );
+                        match reply_correlation_id {
+                            Some(reply_correlation_id) if reply_correlation_id == correlation_id => (),
+                            Some(reply_correlation_id) => {
+                                return Err(anyhow!("Reply correlation id {} does not match request correlation id {}", reply_correlation_id, correlation_id));
+                            }
+                            None => return Err(anyhow!("Reply has no correlation id, expected {}", correlation_id)),
+                        }
+                    {{ end }}
+                    {{ if and $reply.payload_schema (eq $reply.schema_format "json_schema") (ne $reply.content_type "application/octet-stream") }}
+                        validate_message_schema(
+                            Path::new("./src/schemas/{{ $reply.unique_id }}_payload_schema.json"),
+                            &reply_payload,
+                        ).map_err(|e| anyhow!(e))?;
+                    {{ end }}
+                    Ok(serde_json::from_value(reply_payload)?)
+                }
+            {{ end }}
+        {{ end }}
@@ -1,114 +1,121 @@
-use async_nats::{Client, Message, jetstream};
-use async_nats::jetstream::Context;
-use crate::{publish_message,stream_publish_message,model::*,config::*,policy::policy::*, utils::*};
+use crate::{model::*,config::*,logger::*, policy::policy::enforce_policy, utils::*};
+use anyhow::anyhow;
 use std::{time, path::Path};
 use opentelemetry::global;
 use opentelemetry::trace::Tracer;
-use log::{debug, warn, error};
-
-    {{ $isStream := false }}
+use log::debug;
 
     /// This handler is called when a message is received on channel {{ .unique_id }}
+    /// Errors are retried, except `Rejected` ones, see `RetryPolicy`
     /// Channel messages:
     /// {{ range .messages }}
     ///     {{ .unique_id }}
     /// {{ end }}
-    {{if key_exists . "original_operation" "bindings" "nats" "streamname"}}
-        {{ $isStream := (.original_operation.bindings.nats.streamname) }}
-    {{end}}
-    {{if $isStream}}
-        pub fn stream_handler_{{ .unique_id }}(message: jetstream::Message, client: &Client) {
-        let tracer = global::tracer("stream_handler_{{ .unique_id }}");
-        let _span = tracer.start("{{ .unique_id }}_stream_handler");
-        {{ range .messages }}
-                {{ if .payload}}
-                    let payload = match serde_json::from_slice::<serde_json::Value>(&message.message.payload) {
-                        Ok(payload) => payload,
-                        Err(_) => {
-                            error!("Failed to deserialize message payload, make sure payload is a valid json: {{ .unique_id }}\nOriginal message: {:#?}", message);
-                            return;
-                        }
-                    };
-                    {{ if .payload_schema}}
-                        if let Err(e) =validate_message_schema(
-                            Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"),
-                            &payload,
-                        ) {
-                            error!("Failed to validate message schema: {{ .unique_id }}\nOriginal message: {:#?}\nError: {}", message, e);
-                            return;
-                        }
-                    {{ end }}
-                    match serde_json::from_value::<{{ .payload.struct_reference }}>(payload) {
-                        Ok(deserialized_message) => {
-                            debug!("Received message {:#?}", deserialized_message);
-                            let policy_reply = opa_eval(&deserialized_message);
-                            // TODO: Replace this with your own handler code
-                            {{ if eq .payload.model_type "enum"}}
-                                match deserialized_message {
-                                    {{$enumName := .payload.unique_id}}
-                                    {{ range .payload.related_models }}
-                                        {{ $enumName }}::{{ .unique_id }}(payload) => {
-                                        // TODO: Replace this with your own handler code
-                                        println!("Received message payload {{ .unique_id }} {:?}", payload);
-                                        }
-                                    {{ end }}
-                                }
-                            {{ end }}
-                        },
-                        Err(_) => {
-                            warn!("Failed to deserialize message payload: {{ .unique_id }}\nOriginal message: {:#?}", message);
-                            // TODO: Handle the failed deserialization here
-                        },
-                    }
-                {{ end }}
-            {{ end }}
-        }
-    {{else}}
-        pub async fn handler_{{ .unique_id }}(message: Message, client: &Client) {
+        pub async fn handler_{{ .unique_id }}<B: MessageBroker>(message: IncomingMessage, client: &B) -> anyhow::Result<()> {
             let tracer = global::tracer("handler_{{ .unique_id }}");
             let _span = tracer.start("{{ .unique_id }}_handler");
             {{ range .messages }}
                 {{ if .payload}}
-                    let payload = match serde_json::from_slice::<serde_json::Value>(&message.payload) {
+                    let payload = match {{ if eq .schema_format "protobuf" }}decode_protobuf_payload::<{{ .payload.struct_reference }}>(&message.payload){{ else }}decode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), &message.payload){{ end }} {
                         Ok(payload) => payload,
-                        Err(_) => {
-                            error!("Failed to deserialize message payload, make sure payload is a valid json: {{ .unique_id }}\nOriginal message: {:#?}", message);
-                            return;
+                        Err(e) => {
+                            count("{{ $.unique_id }}", Counter::ValidationErrors);
+                            return Err(Rejected(anyhow!("Failed to deserialize message payload, make sure payload is valid {{ .content_type }}: {{ .unique_id }}\nError: {}", e)).into());
                         }
                     };
-                    {{ if .payload_schema}}
+                    {{ if and .payload_schema (eq .schema_format "json_schema") (ne .content_type "application/octet-stream") }}
                         if let Err(e) =validate_message_schema(
                             Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"),
                             &payload,
                         ) {
-                            error!("Failed to validate message schema: {{ .unique_id }}\nOriginal message: {:#?}\nError: {}", message, e);
-                            return;
+                            count("{{ $.unique_id }}", Counter::ValidationErrors);
+                            return Err(Rejected(anyhow!("Failed to validate message schema: {{ .unique_id }}\nError: {}", e)).into());
+                        }
+                    {{ end }}
+                    {{ if .correlation_id_location }}
+                        let correlation_id = extract_correlation_id("{{ .correlation_id_location }}", message.headers.as_ref(), &payload);
+                        if let Some(correlation_id) = &correlation_id {
+                            set_log_correlation_id(correlation_id);
                         }
+                    {{ else if $.reply }}
+                        let correlation_id = None;
                     {{ end }}
+                    enforce_policy("{{ $.unique_id }}", &message, &payload, client).await?;
                 match serde_json::from_value::<{{ .payload.struct_reference }}>(payload) {
                     Ok(deserialized_message) => {
-                        let policy_reply = opa_eval(&deserialized_message);
-                        {{ if eq .payload.model_type "enum"}}
+                        {{ if $.reply }}
+                            {{ $reply := index $.reply.messages 0 }}
+                            match reply_{{ .unique_id }}(deserialized_message, correlation_id.clone()).await {
+                                Ok(reply) => {
+                                    let mut reply_payload = match serde_json::to_value(&reply) {
+                                        Ok(reply_payload) => reply_payload,
+                                        Err(e) => {
+                                            return Err(anyhow!("Failed to serialize reply payload: {{ $reply.unique_id }}\nError: {}", e));
+                                        }
+                                    };
+                                    let mut headers = HeaderMap::new();
+                                    if let Some(correlation_id) = &correlation_id {
+                                        insert_correlation_id(
+                                            "{{ if $reply.correlation_id_location }}{{ $reply.correlation_id_location }}{{ else }}{{ .correlation_id_location }}{{ end }}",
+                                            &mut headers,
+                                            &mut reply_payload,
+                                            correlation_id,
+                                        ).map_err(|e| anyhow!(e))?;
+                                    }
+                                    // answer on the inbox of the requester, fall back to the reply channel
+                                    let reply_subject = match &message.reply {
+                                        Some(reply_subject) => reply_subject.clone(),
+                                        None => config().channels.{{ $.reply.unique_id }}.subject.clone(),
+                                    };
+                                    match {{ if eq $reply.schema_format "protobuf" }}encode_protobuf_payload::<{{ $reply.payload.struct_reference }}>(&reply_payload){{ else }}encode_payload("{{ $reply.content_type }}", Path::new("./src/schemas/{{ $reply.unique_id }}_payload_schema.json"), &reply_payload){{ end }} {
+                                        Ok(reply_payload) => {
+                                            if let Err(e) = client.reply(&reply_subject, headers, &reply_payload).await {
+                                                return Err(anyhow!("Failed to send reply {{ $reply.unique_id }} to {}: {}", reply_subject, e));
+                                            }
+                                        }
+                                        Err(e) => return Err(anyhow!("Failed to serialize reply payload: {{ $reply.unique_id }}\nError: {}", e)),
+                                    }
+                                }
+                                Err(e) => return Err(anyhow!("Failed to build reply for {{ .unique_id }}: {}", e)),
+                            }
+                        {{ else if eq .payload.model_type "enum"}}
                             match deserialized_message {
                                 {{$enumName := .payload.unique_id}}
+                                {{$messageName := .unique_id}}
                                 {{ range .payload.related_models }}
                                     {{ $enumName }}::{{ .unique_id }}(payload) => {
                                     // TODO: Replace this with your own handler code
-                                    debug!("Received message payload {{ .unique_id }} {:?}", payload);
+                                    debug!("Received message payload {{ .unique_id }} {}", redacted("./src/schemas/{{ $messageName }}_payload_schema.json", &payload));
                                     }
                                 {{ end }}
                             }
                         {{else}}
-                            debug!("Received message {:#?}", deserialized_message);
+                            debug!("Received message {}", redacted("./src/schemas/{{ .unique_id }}_payload_schema.json", &deserialized_message));
                             // TODO: Replace this with your own handler code
                         {{ end }}
                     },
-                    Err(_) => {
-                        warn!("Failed to deserialize message payload: {{ .unique_id }}\nOriginal message: {:#?}", message);
+                    Err(e) => {
                         // TODO: Handle the failed deserialization here
+                        count("{{ $.unique_id }}", Counter::ValidationErrors);
+                        return Err(Rejected(anyhow!("Failed to deserialize message payload: {{ .unique_id }}\nError: {}", e)).into());
                     },
                 }
                 {{ end }}
             {{ end }}
+            Ok(())
         }
-    {{end}}
+
+        {{ if .reply }}
+            {{ $reply := index .reply.messages 0 }}
+            {{ range .messages }}
+                {{ if .payload }}
+                    /// Builds the reply to a request received on channel {{ $.unique_id }}
+                    /// the reply is sent back to the requester on the {{ $.reply.unique_id }} channel
+                    pub async fn reply_{{ .unique_id }}(request: {{ .payload.struct_reference }}, correlation_id: Option<String>) -> anyhow::Result<{{ if $reply.payload }} {{ $reply.payload.struct_reference }} {{ else }} () {{ end }}> {
+                        debug!("Received request {} with correlation id {:?}", redacted("./src/schemas/{{ .unique_id }}_payload_schema.json", &request), correlation_id);
+                        // TODO: Replace this with your own handler code
+                        Err(anyhow!("No reply implemented for {{ .unique_id }}"))
+                    }
+                {{ end }}
+            {{ end }}
+        {{ end }}
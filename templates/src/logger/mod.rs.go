@@ -1,5 +1,17 @@
 use std::str::FromStr;
 use log::LevelFilter;
+use crate::config::config;
+use crate::utils::IncomingMessage;
+use lazy_static::lazy_static;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::sync::Mutex;
 
 #[derive(Debug)]
 pub enum LogLevelParseError {
@@ -25,10 +37,227 @@ impl FromStr for LogLevel {
     }
 }
 
-pub fn init_logger(log_level: &str) {
+/// writes the logs as text (`LOG_FORMAT = "pretty"`, the default) or as one json object per line (`LOG_FORMAT = "json"`),
+/// logs written while a message is handled carry the fields of its `MessageContext`
+pub fn init_logger(log_level: &str, log_format: &str) {
     let log_level: LevelFilter = match log_level.parse::<LogLevel>() {
         Ok(LogLevel(level)) => level,
         Err(_) => panic!("Invalid log level"),
     };
-    env_logger::Builder::new().filter(None, log_level).init();
-}
\ No newline at end of file
+    let mut builder = env_logger::Builder::new();
+    builder.filter(None, log_level);
+    match log_format {
+        "pretty" => builder.format(|buf, record| {
+            let context = MESSAGE_CONTEXT
+                .try_with(|context| context.borrow().fields())
+                .unwrap_or_default();
+            let context: String = context.iter().map(|(key, value)| format!(" {}={}", key, value)).collect();
+            writeln!(
+                buf,
+                "[{} {} {}] {}{}",
+                buf.timestamp_millis(),
+                buf.default_styled_level(record.level()),
+                record.target(),
+                record.args(),
+                context
+            )
+        }),
+        "json" => builder.format(|buf, record| {
+            let mut line = Map::new();
+            line.insert("timestamp".to_string(), buf.timestamp_millis().to_string().into());
+            line.insert("level".to_string(), record.level().as_str().into());
+            line.insert("target".to_string(), record.target().into());
+            line.insert("message".to_string(), record.args().to_string().into());
+            let _ = MESSAGE_CONTEXT.try_with(|context| {
+                for (key, value) in context.borrow().fields() {
+                    line.insert(key.to_string(), value.into());
+                }
+            });
+            writeln!(buf, "{}", Value::Object(line))
+        }),
+        other => panic!("Invalid LOG_FORMAT {}, use json or pretty", other),
+    };
+    builder.init();
+}
+
+tokio::task_local! {
+    static MESSAGE_CONTEXT: RefCell<MessageContext>;
+}
+
+/// what the logs written while a message is handled are about
+#[derive(Clone, Debug, Default)]
+pub struct MessageContext {
+    pub channel: String,
+    /// the `operationId` the channel is received with in the spec
+    pub operation_id: Option<&'static str>,
+    /// the `message_id` header of the message, a new id for messages without one
+    pub message_id: String,
+    /// set by the handler once it read the correlation id of the message
+    pub correlation_id: Option<String>,
+    pub trace_id: Option<String>,
+}
+
+impl MessageContext {
+    /// the context of `message` received on `channel`, handled in the trace of `context`
+    pub fn new(channel: &str, message: &IncomingMessage, context: &Context) -> Self {
+        let message_id = message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get("message_id"))
+            .map(|message_id| message_id.as_str().to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let span = context.span();
+        let span_context = span.span_context();
+        MessageContext {
+            channel: channel.to_string(),
+            operation_id: operation_id(channel),
+            message_id,
+            correlation_id: None,
+            trace_id: span_context.is_valid().then(|| span_context.trace_id().to_string()),
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("channel", self.channel.clone())];
+        if let Some(operation_id) = self.operation_id {
+            fields.push(("operation_id", operation_id.to_string()));
+        }
+        fields.push(("message_id", self.message_id.clone()));
+        if let Some(correlation_id) = &self.correlation_id {
+            fields.push(("correlation_id", correlation_id.clone()));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            fields.push(("trace_id", trace_id.clone()));
+        }
+        fields
+    }
+}
+
+/// runs `future` with `context` added to its logs
+pub async fn with_message_context<F: Future>(context: MessageContext, future: F) -> F::Output {
+    MESSAGE_CONTEXT.scope(RefCell::new(context), future).await
+}
+
+/// adds the correlation id to the logs of the message that is handled, outside of a handler it is ignored
+pub fn set_log_correlation_id(correlation_id: &str) {
+    let _ = MESSAGE_CONTEXT.try_with(|context| context.borrow_mut().correlation_id = Some(correlation_id.to_string()));
+}
+
+/// the `operationId` of the operation `channel` is received or sent with
+pub fn operation_id(channel: &str) -> Option<&'static str> {
+    match channel {
+        {{ range .publish_channels }}
+        {{ if key_exists (index . 1).original_operation "operationId" }}
+        "{{ (index . 1).unique_id }}" => Some("{{ (index . 1).original_operation.operationId }}"),
+        {{ end }}
+        {{ end }}
+        {{ range .subscribe_channels }}
+        {{ if key_exists (index . 1).original_operation "operationId" }}
+        "{{ (index . 1).unique_id }}" => Some("{{ (index . 1).original_operation.operationId }}"),
+        {{ end }}
+        {{ end }}
+        _ => None,
+    }
+}
+
+/// the payload schemas of the messages received on `channel`
+fn payload_schemas(channel: &str) -> &'static [&'static str] {
+    match channel {
+        {{ range .publish_channels }}
+        "{{ (index . 1).unique_id }}" => &[{{ range (index . 1).messages }}{{ if .payload_schema }}"./src/schemas/{{ .unique_id }}_payload_schema.json", {{ end }}{{ end }}],
+        {{ end }}
+        _ => &[],
+    }
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+lazy_static! {
+    static ref SCHEMAS: Mutex<HashMap<String, Option<Value>>> = Mutex::new(HashMap::new());
+}
+
+/// the payload as json for the logs, with the fields marked `format: password` or `writeOnly` in the schema at `schema_path`
+/// and the fields named in `LOG_REDACT_FIELDS` redacted, unless `LOG_REDACT` is false
+pub fn redacted<T: Serialize>(schema_path: &str, payload: &T) -> String {
+    match serde_json::to_value(payload) {
+        Ok(mut payload) => {
+            redact_with_schemas(&mut payload, &[schema_path]);
+            payload.to_string()
+        }
+        Err(e) => format!("<not serializable: {}>", e),
+    }
+}
+
+/// the raw payload of a message received on `channel` for the logs, redacted like `redacted` if it is json, otherwise only its size
+pub fn redacted_payload(channel: &str, payload: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(payload) {
+        Ok(mut payload) => {
+            redact_with_schemas(&mut payload, payload_schemas(channel));
+            payload.to_string()
+        }
+        Err(_) => format!("<{} bytes>", payload.len()),
+    }
+}
+
+fn redact_with_schemas(payload: &mut Value, schema_paths: &[&str]) {
+    if !config().log_redact {
+        return;
+    }
+    let fields = &config().log_redact_fields;
+    let mut schemas = SCHEMAS.lock().unwrap();
+    for schema_path in schema_paths {
+        // missing or invalid schemas only redact the configured fields
+        let schema = schemas.entry(schema_path.to_string()).or_insert_with(|| {
+            std::fs::read_to_string(schema_path)
+                .ok()
+                .and_then(|schema| serde_json::from_str(&schema).ok())
+        });
+        redact(payload, schema.as_ref(), fields);
+    }
+    if schema_paths.is_empty() {
+        redact(payload, None, fields);
+    }
+}
+
+fn redact(value: &mut Value, schema: Option<&Value>, fields: &[String]) {
+    if schema.is_some_and(is_sensitive) {
+        *value = REDACTED.into();
+        return;
+    }
+    match value {
+        Value::Object(object) => {
+            for (key, field) in object.iter_mut() {
+                if fields.contains(key) {
+                    *field = REDACTED.into();
+                } else {
+                    redact(field, schema.and_then(|schema| property_schema(schema, key)), fields);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact(item, schema.and_then(|schema| schema.get("items")), fields);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn is_sensitive(schema: &Value) -> bool {
+    schema.get("format").and_then(Value::as_str) == Some("password")
+        || schema.get("writeOnly").and_then(Value::as_bool) == Some(true)
+}
+
+/// the schema of the property `key`, also looked up in the `allOf`, `anyOf` and `oneOf` alternatives
+fn property_schema<'a>(schema: &'a Value, key: &str) -> Option<&'a Value> {
+    schema
+        .get("properties")
+        .and_then(|properties| properties.get(key))
+        .or_else(|| {
+            ["allOf", "anyOf", "oneOf"]
+                .iter()
+                .filter_map(|combinator| schema.get(*combinator)?.as_array())
+                .flatten()
+                .find_map(|alternative| property_schema(alternative, key))
+        })
+}
@@ -8,82 +8,119 @@ use clap::Parser;
 use crate::cli::*;
 use utils::*;
 use crate::handler::*;
-use async_nats::jetstream::{self};
 use std::{collections::HashMap};
-use log::info;
+use log::{error, info};
 mod config;
 mod tracing;
 mod logger;
+#[cfg(test)]
+mod tests;
 
 #[tokio::main]
-async fn main() -> Result<(), async_nats::Error> {
-    // Load .env file
-    config::initialize_env();
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Load the config from the environment, the .env file and CONFIG_FILE, listing every missing or malformed setting
+    let config = config::load()?;
 
+    {{ if ne .protocol "ws" }}
     //start warp server
     tokio::spawn(warp_server::server());
+    {{ end }}
 
     // Initialize logger
-    let log_lvl = config::get_env("LOG_LEVEL").unwrap().parse().unwrap_or("INFO".to_string());
-    logger::init_logger(&log_lvl);
+    logger::init_logger(&config.log_level, &config.log_format);
 
     // Initialize tracing
-    let tracing_enabled: bool = config::get_env("TRACING_ENABLED").unwrap().parse().unwrap();
-    if tracing_enabled {
-        let _tracer = tracing::init_jaeger_tracer("{{ .title}}");
+    if config.tracing_enabled {
+        tracing::init_tracer("{{ .title}}");
     }
     
-    // Connect to NATS server
-    let nats_url = config::get_env("SERVER_URL").unwrap();
-    info!("Connecting to a NATS server: {}", nats_url);
-    let client = async_nats::connect(nats_url).await?;
+    let server_url = &config.server_url;
+    if server_url == "memory" {
+        // Run without broker, messages are only exchanged between the channels of the service
+        info!("Using the in-memory broker");
+        {{ if eq .protocol "ws" }}
+        tokio::spawn(warp_server::server(None));
+        {{ end }}
+        run(MemoryBroker::new()).await?;
+    } else {
+        {{ if eq .protocol "kafka" }}
+        // Connect to the Kafka cluster
+        info!("Connecting to a Kafka cluster: {}", server_url);
+        let client = connect(&server_url)?;
+        {{ else if eq .protocol "mqtt" }}
+        // Connect to the MQTT broker
+        info!("Connecting to a MQTT broker: {}", server_url);
+        let client = connect(&server_url).await?;
+        {{ else if eq .protocol "amqp" }}
+        // Connect to the AMQP broker
+        info!("Connecting to an AMQP broker: {}", server_url);
+        let client = connect(&server_url).await?;
+        {{ else if eq .protocol "ws" }}
+        // Accept websocket connections or connect to the websocket server
+        let client = connect(&server_url).await?;
 
-    // Subscribe to channels
-    {{ range .publish_channels }}
-        {{ if (index . 1).original_operation.bindings }}
-                {{ if (index . 1).original_operation.bindings.nats.queue }}
-                    let mut {{ (index . 1).unique_id }} = client.queue_subscribe(config::get_env("{{ (index . 1).unique_id}}_SUBJECT").unwrap().into(),
-                     config::get_env("{{ (index . 1).unique_id}}_QUEUE").unwrap().into()).await?;
-                {{ else  }}
-                    let clientcpy = client.clone();
-                    let context_jetstream = jetstream::new(clientcpy);
-                    let {{ (index . 1).unique_id }} = config::get_env("{{ (index . 1).unique_id }}_STREAM").unwrap();
-                    let consumer = get_consumer(&context_jetstream, &{{ (index . 1).unique_id }}).await?;
-                {{end}}
+        //start warp server, it serves the websocket connections in server mode
+        tokio::spawn(warp_server::server(Some(client.clone())));
+        {{ else if eq .protocol "mercure" }}
+        // Use the Mercure hub
+        info!("Using the Mercure hub: {}", server_url);
+        let client = connect(&server_url).await?;
+        {{ else if eq .protocol "redis" }}
+        // Connect to the Redis server
+        info!("Connecting to a Redis server: {}", server_url);
+        let client = connect(&server_url).await?;
         {{ else }}
-            let mut {{ (index . 1).unique_id }} = client.subscribe(config::get_env("{{ (index . 1).unique_id}}_SUBJECT").unwrap().into()).await?;
-        {{end}}
-    {{end}}
+        // Connect to NATS server
+        info!("Connecting to a NATS server: {}", server_url);
+        let client = connect(&server_url).await?;
+        {{ end }}
+        run(client).await?;
+    }
+
+    // Export the remaining spans
+    if config.tracing_enabled {
+        tracing::shutdown_tracer_provider();
+    }
+    info!("Shutting down...");
+    Ok(())
+}
+
+/// subscribes to the channels, sends the message given on the command line and passes the received messages to the handlers
+async fn run<B: MessageBroker>(client: B) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Subscribe to channels, the broker applies the settings of their bindings (e.g. queue groups, consumer groups, qos)
+    {{ range .publish_channels }}
+        let mut {{ (index . 1).unique_id }} = client.subscribe(
+            "{{ (index . 1).unique_id }}",
+            &config::config().channels.{{ (index . 1).unique_id }}.subject,
+        ).await?;
+        set_listening("{{ (index . 1).unique_id }}", true);
+    {{ end }}
 
     // Parse CLI arguments
     let args = cli::Args::parse();
     handle_cli(&client, &args.command, &args.message).await?;
 
-    // Listen for messages
+    // On SIGINT or SIGTERM report not ready and stop listening, the handled messages get SHUTDOWN_TIMEOUT_MS to finish
+    let (trigger, shutdown) = Shutdown::new();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Received shutdown signal, finishing the handled messages");
+        set_ready(false);
+        trigger.trigger();
+    });
+    set_ready(true);
+
+    // Listen for messages, readiness follows the broker connection
     tokio::join!(
-    {{ range .subscribe_channels }}
-        {{ $isStream := false }}
-        {{if key_exists (index . 1) "original_operation" "bindings" "nats" "streamname"}}
-            {{ $isStream := ((index . 1).original_operation.bindings.nats.streamname) }}
-        {{end}}
-    {{ end }}
+        watch_connection(&client, shutdown.clone()),
     {{ range .publish_channels  }}
-        {{ $isStream := false }}
-        {{if key_exists (index . 1) "original_operation" "bindings" "nats" "streamname"}}
-            {{ $isStream := ((index . 1).original_operation.bindings.nats.streamname) }}
-        {{end}}
-        {{if $isStream}}
-            stream_listen_for_message(&consumer, stream_handler_{{ (index . 1).unique_id }}, &client),
-        {{ else }}
-            listen_for_message(&mut  {{ (index . 1).unique_id }}, handler_{{ (index . 1).unique_id }}, &client),
-        {{ end }}
+        listen_for_message("{{ (index . 1).unique_id }}", &mut {{ (index . 1).unique_id }}, handler_{{ (index . 1).unique_id }}, &client, shutdown.clone()),
     {{ end }}
     );
 
-    // Shutdown Jaeger Tracer
-    if tracing_enabled {
-        tracing::shutdown_tracer_provider();
+    // Send the messages the handlers published before exiting
+    if let Err(e) = client.flush().await {
+        error!("Failed to flush published messages: {}", e);
     }
-    info!("Shutting down...");
     Ok(())
 }
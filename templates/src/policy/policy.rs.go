@@ -1,76 +1,255 @@
+use crate::config::config;
+use crate::logger::operation_id;
+use crate::utils::*;
 use anyhow::{anyhow, Result};
-use opa_wasm::Runtime;
-use reqwest::{self, Body, Client, IntoUrl, Response};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use opa_wasm::{DefaultContext, Policy, Runtime};
+use reqwest::Client;
 use serde::Serialize;
-use std::env;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use tokio::sync::OnceCell;
 use wasmtime::{Config, Engine, Module, Store};
 
+lazy_static! {
+    /// reuses the connections to the opa server
+    static ref CLIENT: Client = Client::new();
+}
+
+/// the wasm policy, compiled once on its first evaluation
+static WASM_POLICY: OnceCell<WasmPolicy> = OnceCell::const_new();
+
+/// the compiled wasm policy and its idle instances, an evaluation takes one of them or instantiates another one,
+/// so there are as many instances as evaluations run at the same time
+struct WasmPolicy {
+    engine: Engine,
+    module: Module,
+    idle: Mutex<Vec<WasmInstance>>,
+}
+
+/// an instance of the wasm policy with its data loaded, it evaluates one input at a time
+struct WasmInstance {
+    store: Store<()>,
+    policy: Policy<DefaultContext>,
+}
+
+impl WasmPolicy {
+    async fn instantiate(&self) -> Result<WasmInstance> {
+        let mut store = Store::new(&self.engine, ());
+        let runtime = Runtime::new(&mut store, &self.module).await?;
+        let policy = runtime.with_data(&mut store, &serde_json::json!({})).await?;
+        Ok(WasmInstance { store, policy })
+    }
+}
+
+/// whether the policy is checked for a message the service receives or one its producers send
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Receive,
+    Send,
+}
+
+/// what the policy decides on, available as `input` in rego
+#[derive(Debug, Serialize)]
+pub struct PolicyInput<'a, T: Serialize> {
+    pub channel: &'a str,
+    /// the `operationId` of the channel in the spec
+    pub operation: Option<&'a str>,
+    pub action: Action,
+    pub subject: &'a str,
+    pub headers: BTreeMap<String, String>,
+    pub message: &'a T,
+}
+
+/// the outcome of the policy for a message
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Allow,
+    /// denied with the decision of the policy
+    Deny(serde_json::Value),
+}
+
+/// the error of a handler whose message was denied by the policy, the message is terminated without further attempts
+#[derive(Debug)]
+pub struct PolicyDenied(pub serde_json::Value);
+
+impl fmt::Display for PolicyDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Denied by policy: {}", self.0)
+    }
+}
+
+impl std::error::Error for PolicyDenied {}
+
+/// checks the policy for `message` sent or received on `channel`, every message is allowed unless `OPA_ENABLED` is true
+pub async fn check_policy<T: Serialize>(
+    channel: &str,
+    action: Action,
+    subject: &str,
+    headers: Option<&HeaderMap>,
+    message: &T,
+) -> Result<Decision> {
+    if !config().opa.enabled {
+        return Ok(Decision::Allow);
+    }
+    let input = PolicyInput {
+        channel,
+        operation: operation_id(channel),
+        action,
+        subject,
+        headers: header_values(headers),
+        message,
+    };
+    let decision = opa_eval(&input).await?;
+    match policy_allows(&decision) {
+        true => Ok(Decision::Allow),
+        false => Ok(Decision::Deny(decision)),
+    }
+}
+
+/// checks the policy for a received `message` with its payload decoded by the handler of `channel`,
+/// a denied message is routed like `route_denied` and fails the handler with `PolicyDenied`,
+/// failed evaluations fail it with their error, so they are retried like the handler
+pub async fn enforce_policy<B: MessageBroker, T: Serialize>(
+    channel: &str,
+    message: &IncomingMessage,
+    decoded: &T,
+    client: &B,
+) -> Result<()> {
+    let decision = check_policy(channel, Action::Receive, &message.subject, message.headers.as_ref(), decoded).await?;
+    let Decision::Deny(decision) = decision else {
+        return Ok(());
+    };
+    let headers = message.headers.clone().unwrap_or_default();
+    route_denied(client, channel, &message.subject, headers, &message.payload, &decision)
+        .await
+        .map_err(|e| anyhow!("Failed to route denied message on {}: {}", message.subject, e))?;
+    Err(PolicyDenied(decision).into())
+}
+
+/// counts and logs a denied message and republishes it with its headers on the `{channel}_POLICY_DENIED_SUBJECT`, if that is set,
+/// with the decision in the `x-policy-decision` header
+pub async fn route_denied<B: MessageBroker>(
+    client: &B,
+    channel: &str,
+    subject: &str,
+    mut headers: HeaderMap,
+    payload: &[u8],
+    decision: &serde_json::Value,
+) -> Result<()> {
+    count(channel, Counter::PolicyDenials);
+    let denied_subject = config()
+        .channels
+        .get(channel)
+        .and_then(|settings| settings.policy_denied_subject.as_ref());
+    match denied_subject {
+        Some(denied_subject) => {
+            warn!("Message on {} denied by policy, routed to {}: {}", subject, denied_subject, decision);
+            headers.insert("x-policy-decision", decision.to_string().as_str());
+            headers.insert("x-policy-channel", channel);
+            headers.insert("x-policy-subject", subject);
+            client.publish("", denied_subject, headers, payload).await
+        }
+        None => {
+            warn!("Message on {} denied by policy: {}", subject, decision);
+            Ok(())
+        }
+    }
+}
+
+fn header_values(headers: Option<&HeaderMap>) -> BTreeMap<String, String> {
+    let Some(headers) = headers else {
+        return BTreeMap::new();
+    };
+    headers
+        .iter()
+        {{ if eq .protocol "nats" }}
+        .map(|(name, values)| {
+            let values: Vec<&str> = values.iter().map(|value| value.as_str()).collect();
+            (name.to_string(), values.join(","))
+        })
+        {{ else }}
+        .map(|(name, value)| (name.clone(), value.clone()))
+        {{ end }}
+        .collect()
+}
+
+/// evaluates the policy for `input` if `OPA_ENABLED` is true, otherwise every input is allowed,
+/// check the decision with `policy_allows`
 pub async fn opa_eval<I>(input: &I) -> Result<serde_json::Value>
 where
     I: Serialize,
 {
-    if let Ok(enabled) = env::var("OPA_ENABLED") {
-        let enabled: bool = enabled.parse().unwrap();
-        if enabled == false {
-            return Ok(serde_json::to_value(true).unwrap());
-        }
+    let opa = &config().opa;
+    if !opa.enabled {
+        return Ok(serde_json::Value::Bool(true));
     }
-    if let Ok(url) = env::var("OPA_REMOTE_URL") {
-        let url: String = url.parse().unwrap();
-        return opa_eval_remote(url, serde_json::to_string(&input)?).await;
+    if let Some(url) = &opa.remote_url {
+        return opa_eval_remote(url, input).await;
     }
-    if let Ok(path) = env::var("OPA_LOCAL_WASM_PATH") {
-        let path: String = path.parse().unwrap();
-        return opa_eval_wasm(tokio::fs::read(path).await.unwrap(), input, "").await;
+    if let Some(path) = &opa.local_wasm_path {
+        return opa_eval_wasm(path, &opa.wasm_entrypoint, input).await;
     }
 
     return Err(anyhow!("No OPA method provided"));
 }
 
-pub async fn opa_eval_remote<I>(url: impl IntoUrl, input: I) -> Result<serde_json::Value>
-where
-    Body: From<I>,
-{
-    let client = Client::new();
-    let response: Response = client
+/// whether a decision allows the input: `true`, an object whose `allow` field is `true`,
+/// or such a decision in the `result` of the opa server or the first result of a wasm policy
+pub fn policy_allows(decision: &serde_json::Value) -> bool {
+    match decision {
+        serde_json::Value::Bool(allowed) => *allowed,
+        serde_json::Value::Object(fields) => fields
+            .get("result")
+            .or_else(|| fields.get("allow"))
+            .is_some_and(policy_allows),
+        serde_json::Value::Array(results) => results.first().is_some_and(policy_allows),
+        _ => false,
+    }
+}
+
+/// posts `input` to the data api of the opa server at `url`, e.g. `http://localhost:8181/v1/data/app/allow`
+pub async fn opa_eval_remote<I: Serialize>(url: &str, input: &I) -> Result<serde_json::Value> {
+    let response = CLIENT
         .post(url)
         .header("Content-Type", "application/json")
-        .body(input)
+        .body(serde_json::to_vec(&serde_json::json!({ "input": input }))?)
         .send()
-        .await?;
-    println!("Response status: {}", response.status());
-
-    let response_body = response.bytes().await?;
-    println!("Response body: {:?}", response_body);
-
-    Ok(serde_json::from_slice(response_body.as_ref())?)
+        .await?
+        .error_for_status()?;
+    let decision: serde_json::Value = serde_json::from_slice(&response.bytes().await?)?;
+    debug!("Policy decision of {}: {}", url, decision);
+    Ok(decision)
 }
 
-pub async fn opa_eval_wasm(
-    policy: impl AsRef<[u8]>,
-    input: impl serde::Serialize,
-    data: &str,
-) -> Result<serde_json::Value> {
-    // Configure the WASM runtime
-    let mut config = Config::new();
-    config.async_support(true);
-
-    let engine = Engine::new(&config)?;
-
-    // Load the policy WASM module
-    let module = Module::new(&engine, policy)?;
-
-    // Create a store which will hold the module instance
-    let mut store = Store::new(&engine, ());
-
-    // Instantiate the module
-    let runtime = Runtime::new(&mut store, &module).await?;
-
-    let policy = runtime.with_data(&mut store, &data).await?;
-
-    // Evaluate the policy
-    let response: serde_json::Value = policy.evaluate(&mut store, data, &input).await?;
+/// evaluates `entrypoint` of the policy compiled to wasm at `path`, the module is compiled on the first call
+/// and its instances are reused by later evaluations
+pub async fn opa_eval_wasm(path: &str, entrypoint: &str, input: impl Serialize) -> Result<serde_json::Value> {
+    let wasm = WASM_POLICY
+        .get_or_try_init(|| async {
+            let mut config = Config::new();
+            config.async_support(true);
+            let engine = Engine::new(&config)?;
+            let module = Module::new(&engine, tokio::fs::read(path).await?)?;
+            Ok::<_, anyhow::Error>(WasmPolicy {
+                engine,
+                module,
+                idle: Mutex::new(Vec::new()),
+            })
+        })
+        .await?;
 
-    println!("{}", response);
-    Ok(response)
+    let idle = wasm.idle.lock().unwrap().pop();
+    let mut instance = match idle {
+        Some(instance) => instance,
+        None => wasm.instantiate().await?,
+    };
+    // an instance whose evaluation failed is dropped instead of being reused
+    let decision: serde_json::Value = instance.policy.evaluate(&mut instance.store, entrypoint, &input).await?;
+    wasm.idle.lock().unwrap().push(instance);
+    debug!("Policy decision of {}: {}", path, decision);
+    Ok(decision)
 }
@@ -0,0 +1,274 @@
+//! Runs the handlers of the service on the in-memory broker, so `cargo test` needs no broker.
+//! Publish a message into a channel with `TestHarness::publish` and assert on the messages the handlers produce with `expect`:
+//! ```ignore
+//! let harness = TestHarness::start().await?;
+//! let mut produced = harness.subscribe("some_sending_channel")?;
+//! harness.publish("some_receiving_channel", &SomeMessage { .. }).await?;
+//! let message: OtherMessage = harness.expect(&mut produced).await?;
+//! ```
+//! Messages are encoded and decoded with the codec of their channel, like the content type or protobuf schema of its messages.
+use crate::{config, handler::*, model::*, tracing::*, utils::*};
+use anyhow::anyhow;
+use opentelemetry::sdk::{propagation::TraceContextPropagator, trace::TracerProvider};
+use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider as _};
+use opentelemetry::{global, Context};
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+/// the handlers of the receiving channels, listening on an in-memory broker
+pub struct TestHarness {
+    pub client: MemoryBroker,
+    shutdown: Shutdown,
+}
+
+/// the messages produced into a channel, see `TestHarness::subscribe`
+pub struct Produced {
+    channel: String,
+    messages: MemorySubscription,
+}
+
+impl TestHarness {
+    /// loads the config and subscribes the handlers to their channels, messages are handled in the background
+    pub async fn start() -> anyhow::Result<Self> {
+        let harness = TestHarness::new()?;
+        let client = harness.client.clone();
+        {{ range .publish_channels }}
+        let mut subscription = client.subscribe("{{ (index . 1).unique_id }}", &subject("{{ (index . 1).unique_id }}")?).await?;
+        let listener = client.clone();
+        let listener_shutdown = harness.shutdown.clone();
+        tokio::spawn(async move { listen_for_message("{{ (index . 1).unique_id }}", &mut subscription, handler_{{ (index . 1).unique_id }}, &listener, listener_shutdown).await });
+        {{ end }}
+        Ok(harness)
+    }
+
+    /// loads the config without subscribing the handlers, so tests can subscribe handlers of their own with `handle`
+    pub fn new() -> anyhow::Result<Self> {
+        config::load()?;
+        // the handlers listen until the test ends, as the trigger is dropped
+        let (_trigger, shutdown) = Shutdown::new();
+        Ok(TestHarness {
+            client: MemoryBroker::new(),
+            shutdown,
+        })
+    }
+
+    /// subscribes `handler` to `channel`, it handles the messages like the handlers of the service, with retries and settlements
+    pub async fn handle<F, Fut>(&self, channel: &str, handler: F) -> anyhow::Result<()>
+    where
+        F: Fn(IncomingMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send,
+    {
+        let mut subscription = self.client.subscribe(channel, &subject(channel)?).await?;
+        let listener = self.client.clone();
+        let listener_shutdown = self.shutdown.clone();
+        let channel = channel.to_string();
+        tokio::spawn(async move {
+            listen_for_message(&channel, &mut subscription, |message, _| handler(message), &listener, listener_shutdown).await
+        });
+        Ok(())
+    }
+
+    /// publishes `message` into `channel` with the codec of the channel, like another service would
+    pub async fn publish<T: Serialize>(&self, channel: &str, message: &T) -> anyhow::Result<()> {
+        let payload = encode(channel, message)?;
+        self.client.publish(channel, &subject(channel)?, HeaderMap::new(), &payload).await
+    }
+
+    /// sends `message` as request into `channel` and returns the reply of the handler, decoded with the codec of the reply channel
+    pub async fn request<T: Serialize, R: DeserializeOwned>(&self, channel: &str, message: &T) -> anyhow::Result<R> {
+        let payload = encode(channel, message)?;
+        let reply = self.client.request(&subject(channel)?, HeaderMap::new(), &payload).await?;
+        Ok(serde_json::from_value(decode_reply(channel, &reply.payload)?)?)
+    }
+
+    /// receives the messages produced into `channel`, subscribe before triggering them
+    pub fn subscribe(&self, channel: &str) -> anyhow::Result<Produced> {
+        Ok(Produced {
+            channel: channel.to_string(),
+            messages: self.client.subscribe_queue(&subject(channel)?, None),
+        })
+    }
+
+    /// waits up to a second for the next message produced into the channel and decodes its payload
+    pub async fn expect<T: DeserializeOwned>(&self, produced: &mut Produced) -> anyhow::Result<T> {
+        match tokio::time::timeout(Duration::from_secs(1), produced.messages.recv()).await {
+            Ok(Some(message)) => Ok(serde_json::from_value(decode(&produced.channel, &message.payload)?)?),
+            Ok(None) => Err(anyhow!("Subscription closed")),
+            Err(_) => Err(anyhow!("No message received within a second")),
+        }
+    }
+}
+
+/// the subject of `channel`, configured by `{channel}_SUBJECT`
+fn subject(channel: &str) -> anyhow::Result<String> {
+    match config::config().channels.get(channel) {
+        Some(settings) => Ok(settings.subject.clone()),
+        None => Err(anyhow!("{} is no channel of the spec", channel)),
+    }
+}
+
+/// encodes `message` with the codec of the first message of `channel` it fits
+#[allow(unused_variables)]
+pub fn encode(channel: &str, message: &impl Serialize) -> anyhow::Result<Vec<u8>> {
+    {{ range .publish_channels }}{{ $channel := index . 1 }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<Vec<u8>, String>(format!("{} has no message with a payload", channel)){{ range $channel.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}encode_protobuf_payload::<{{ .payload.struct_reference }}>(message){{ else }}encode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), message){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}
+    {{ range .subscribe_channels }}{{ $channel := index . 1 }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<Vec<u8>, String>(format!("{} has no message with a payload", channel)){{ range $channel.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}encode_protobuf_payload::<{{ .payload.struct_reference }}>(message){{ else }}encode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), message){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}
+    Err(anyhow!("{} is no channel of the spec", channel))
+}
+
+/// decodes `payload` with the codec of the first message of `channel` that can read it
+#[allow(unused_variables)]
+pub fn decode(channel: &str, payload: &[u8]) -> anyhow::Result<serde_json::Value> {
+    {{ range .publish_channels }}{{ $channel := index . 1 }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<serde_json::Value, String>(format!("{} has no message with a payload", channel)){{ range $channel.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}decode_protobuf_payload::<{{ .payload.struct_reference }}>(payload){{ else }}decode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), payload){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}
+    {{ range .subscribe_channels }}{{ $channel := index . 1 }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<serde_json::Value, String>(format!("{} has no message with a payload", channel)){{ range $channel.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}decode_protobuf_payload::<{{ .payload.struct_reference }}>(payload){{ else }}decode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), payload){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}
+    Err(anyhow!("{} is no channel of the spec", channel))
+}
+
+/// a minimal payload of `message` built from its schema, e.g. for `sum_message` from `src/schemas/sum_message_payload_schema.json`,
+/// which takes the first example, default or enum value of every property and the simplest value of its type otherwise
+pub fn example_payload(message: &str) -> anyhow::Result<serde_json::Value> {
+    let schema = std::fs::read_to_string(format!("./src/schemas/{}_payload_schema.json", message))?;
+    Ok(example(&serde_json::from_str(&schema)?))
+}
+
+fn example(schema: &serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    if let Some(value) = schema.get("const").or_else(|| schema.get("default")) {
+        return value.clone();
+    }
+    for key in ["examples", "enum"] {
+        if let Some(value) = schema.get(key).and_then(Value::as_array).and_then(|values| values.first()) {
+            return value.clone();
+        }
+    }
+    for key in ["oneOf", "anyOf", "allOf"] {
+        if let Some(first) = schema.get(key).and_then(Value::as_array).and_then(|schemas| schemas.first()) {
+            return example(first);
+        }
+    }
+    let schema_type = match schema.get("type") {
+        Some(Value::Array(types)) => types.first().and_then(Value::as_str),
+        schema_type => schema_type.and_then(Value::as_str),
+    };
+    match schema_type {
+        Some("string") => Value::from(""),
+        Some("number") | Some("integer") => Value::from(0),
+        Some("boolean") => Value::from(false),
+        Some("array") => Value::Array(vec![]),
+        Some("null") => Value::Null,
+        // the generated structs need every property, not only the required ones
+        _ => match schema.get("properties").and_then(Value::as_object) {
+            Some(properties) => properties.iter().map(|(name, property)| (name.clone(), example(property))).collect(),
+            None => Value::Object(Default::default()),
+        },
+    }
+}
+
+/// encodes the reply to a request on `channel` with the codec of its reply channel
+#[allow(unused_variables)]
+pub fn encode_reply(channel: &str, message: &impl Serialize) -> anyhow::Result<Vec<u8>> {
+    {{ range .publish_channels }}{{ $channel := index . 1 }}{{ if $channel.reply }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<Vec<u8>, String>(format!("{} has no message with a payload", channel)){{ range $channel.reply.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}encode_protobuf_payload::<{{ .payload.struct_reference }}>(message){{ else }}encode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), message){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}{{ end }}
+    Err(anyhow!("{} has no reply channel", channel))
+}
+
+/// decodes the reply to a request on `channel` with the codec of its reply channel
+#[allow(unused_variables)]
+pub fn decode_reply(channel: &str, payload: &[u8]) -> anyhow::Result<serde_json::Value> {
+    {{ range .publish_channels }}{{ $channel := index . 1 }}{{ if $channel.reply }}
+    if channel == "{{ $channel.unique_id }}" {
+        return Err::<serde_json::Value, String>(format!("{} has no message with a payload", channel)){{ range $channel.reply.messages }}{{ if .payload }}
+            .or_else(|_| {{ if eq .schema_format "protobuf" }}decode_protobuf_payload::<{{ .payload.struct_reference }}>(payload){{ else }}decode_payload("{{ .content_type }}", Path::new("./src/schemas/{{ .unique_id }}_payload_schema.json"), payload){{ end }}){{ end }}{{ end }}
+            .map_err(|e| anyhow!(e));
+    }
+    {{ end }}{{ end }}
+    Err(anyhow!("{} has no reply channel", channel))
+}
+
+#[tokio::test]
+async fn handlers_listen_on_their_channels() {
+    let harness = TestHarness::start().await.unwrap();
+    {{ range .publish_channels }}
+    assert_ne!(harness.client.subscribers(&subject("{{ (index . 1).unique_id }}").unwrap()), 0, "No handler listens on {{ (index . 1).unique_id }}");
+    {{ end }}
+}
+
+{{ if and .publish_channels .subscribe_channels }}
+{{ $handled := index .publish_channels 0 }}{{ $handled_message := index (index $handled 1).messages 0 }}
+{{ $produced := index .subscribe_channels 0 }}{{ $produced_message := index (index $produced 1).messages 0 }}
+{{ if and (ne (index $handled 0) (index $produced 0)) (not (index $handled 1).reply) $handled_message.payload (eq $handled_message.content_type "application/json") (eq $produced_message.content_type "application/json") }}
+// the generated handler_{{ (index $handled 1).unique_id }} only logs the messages until it is implemented, so nothing is produced yet,
+// expect the message it produces into {{ (index $produced 1).unique_id }} once it calls a producer
+#[tokio::test]
+async fn handled_messages_are_produced() {
+    let harness = TestHarness::start().await.unwrap();
+    let mut produced = harness.subscribe("{{ (index $produced 1).unique_id }}").unwrap();
+    let message = example_payload("{{ $handled_message.unique_id }}").unwrap();
+    harness.publish("{{ (index $handled 1).unique_id }}", &message).await.unwrap();
+    let received = harness.expect::<serde_json::Value>(&mut produced).await;
+    assert!(received.is_err(), "Unexpected message produced into {{ (index $produced 1).unique_id }}: {:?}", received);
+}
+{{ end }}
+{{ end }}
+
+{{ range .publish_channels }}{{ $channel := index . 1 }}{{ if $channel.reply }}
+{{ $request := index $channel.messages 0 }}{{ $reply := index $channel.reply.messages 0 }}
+{{ if and $request.payload (eq $request.content_type "application/json") (eq $reply.content_type "application/json") }}
+// the generated reply_{{ $request.unique_id }} fails until it is implemented, so the handler sends no reply and the request times out,
+// expect the reply it builds once it is implemented
+#[tokio::test]
+async fn requests_on_{{ $channel.unique_id }}_are_replied() {
+    let harness = TestHarness::start().await.unwrap();
+    let request = example_payload("{{ $request.unique_id }}").unwrap();
+    let reply = harness.request::<_, serde_json::Value>("{{ $channel.unique_id }}", &request).await;
+    assert!(reply.is_err(), "Unexpected reply to a request on {{ $channel.unique_id }}: {:?}", reply);
+}
+{{ end }}
+{{ end }}{{ end }}
+
+#[test]
+fn trace_context_is_propagated_in_headers() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    // the tracer only creates valid spans while its provider lives
+    let provider = TracerProvider::builder().build();
+    let tracer = provider.tracer("tests");
+    let context = Context::current_with_span(tracer.start("producer"));
+    let mut headers = HeaderMap::new();
+    inject_context(&context, &mut headers);
+    let extracted = extract_context(Some(&headers));
+    assert!(extracted.span().span_context().is_remote());
+    assert_eq!(
+        extracted.span().span_context().trace_id(),
+        context.span().span_context().trace_id()
+    );
+}
@@ -1,13 +1,67 @@
-use opentelemetry::global;
+use crate::config::config;
+use crate::utils::HeaderMap;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
 
-pub fn init_jaeger_tracer(service_name: &str) {
-    global::set_text_map_propagator(opentelemetry_jaeger::Propagator::new());
-    opentelemetry_jaeger::new_agent_pipeline()
-        .with_service_name(service_name)
+/// the headers of the w3c trace context
+const TRACE_HEADERS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// exports the spans over grpc to the OTLP collector at `OTEL_EXPORTER_OTLP_ENDPOINT` (http://localhost:4317)
+/// and propagates the trace context in the w3c `traceparent` and `tracestate` message headers
+pub fn init_tracer(service_name: &str) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let endpoint = config().otlp_endpoint.clone();
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )])))
         .install_batch(opentelemetry::runtime::Tokio)
-        .expect("Failed to initialize Jaeger Tracer");
+        .expect("Failed to initialize OTLP Tracer");
 }
 
+/// exports the remaining spans
 pub fn shutdown_tracer_provider() {
-    opentelemetry::global::shutdown_tracer_provider();
-}
\ No newline at end of file
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key, value.as_str());
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(|value| value.as_str())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        TRACE_HEADERS
+            .into_iter()
+            .filter(|key| self.0.get(*key).is_some())
+            .collect()
+    }
+}
+
+/// writes the trace context of `context` to the headers of an outgoing message
+pub fn inject_context(context: &Context, headers: &mut HeaderMap) {
+    global::get_text_map_propagator(|propagator| propagator.inject_context(context, &mut HeaderInjector(headers)));
+}
+
+/// the trace context the producer of a message sent in its headers, the spans of the handler continue its trace
+pub fn extract_context(headers: Option<&HeaderMap>) -> Context {
+    match headers {
+        Some(headers) => global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers))),
+        None => Context::new(),
+    }
+}
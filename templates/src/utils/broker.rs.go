@@ -0,0 +1,287 @@
+use super::{count, observe_handler_duration, set_listening, Counter, Handling, HeaderMap, Shutdown};
+use crate::config::{config, RetryPolicy};
+use crate::logger::{redacted_payload, with_message_context, MessageContext};
+use crate::policy::policy::PolicyDenied;
+use crate::tracing::extract_context;
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{error, warn};
+use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
+use opentelemetry::global;
+use std::fmt;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// a message received on a channel, the same for every protocol so handlers do not depend on the broker
+#[derive(Clone, Debug)]
+pub struct IncomingMessage {
+    /// subject, topic, routing key, path or stream the message was received on
+    pub subject: String,
+    /// where the reply to a request is sent, if the protocol supports replies
+    pub reply: Option<String>,
+    pub payload: Vec<u8>,
+    pub headers: Option<HeaderMap>,
+}
+
+/// how a received message is settled with the broker once the handler is done with it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Settlement {
+    /// the message was handled
+    Ack,
+    /// the message failed but may succeed later, the broker delivers it again
+    Nak,
+    /// the message failed for good or was dead-lettered, the broker must not deliver it again
+    Term,
+}
+
+/// the error of a message that can never be handled, e.g. an invalid payload, it is not retried
+#[derive(Debug)]
+pub struct Rejected(pub anyhow::Error);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// the operations the service needs from a broker, every backend implements it for its `Client`
+/// channels are passed by their id in the spec, so backends can apply the settings of their bindings (e.g. qos, queue or consumer group)
+#[async_trait]
+pub trait MessageBroker: Send + Sync {
+    /// receives the messages of one channel
+    type Subscription: Send;
+    /// a received message together with what the broker needs to acknowledge it
+    type Delivery: Send + Sync;
+
+    /// publishes `payload` on `subject` with the settings of `channel`, which is empty for subjects outside the spec
+    async fn publish(&self, channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()>;
+
+    /// publishes with a message key, messages with the same key keep their order on brokers with partitions, the others ignore the key
+    async fn publish_keyed(
+        &self,
+        channel: &str,
+        subject: &str,
+        _key: Option<&str>,
+        headers: HeaderMap,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        self.publish(channel, subject, headers, payload).await
+    }
+
+    /// subscribes to `subject` with the settings of `channel`
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<Self::Subscription>;
+
+    /// waits for the next message of `subscription`, `None` once the subscription ended
+    async fn next(&self, subscription: &mut Self::Subscription) -> Option<(IncomingMessage, Self::Delivery)>;
+
+    /// acks, naks or terminates a delivery, brokers without acknowledgements ignore it
+    async fn ack(&self, delivery: Self::Delivery, settlement: Settlement) -> anyhow::Result<()>;
+
+    /// tells the broker a delivery is still handled, e.g. before waiting for a retry, so it is not delivered again meanwhile
+    /// brokers without an acknowledgement timeout ignore it
+    async fn in_progress(&self, _delivery: &Self::Delivery) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// sends a request on `subject` and waits up to `REQUEST_TIMEOUT_MS` for the reply
+    async fn request(&self, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<IncomingMessage>;
+
+    /// answers a request on the `reply` subject of the request or the reply channel
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()>;
+
+    /// stops the broker from sending further messages to `subscription` on shutdown,
+    /// the messages already received are still handled and acknowledged
+    async fn unsubscribe(&self, _subscription: &mut Self::Subscription) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// waits until the published messages were sent, brokers whose `publish` returns once the message was sent need nothing to flush
+    async fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// whether the connection to the broker is up, brokers that reconnect on every request are always connected
+    fn connected(&self) -> bool {
+        true
+    }
+}
+
+/// passes every message of `subscription` to `handler`, up to `{channel}_MAX_IN_FLIGHT` (1) messages at the same time,
+/// retries failed messages with the retry policy of `channel` and settles them depending on the result:
+/// handled messages are acked, rejected ones and ones denied by the policy terminated and others nacked,
+/// messages that failed every attempt are terminated once they were republished on the dead letter subject.
+/// Once `shutdown` is triggered no further messages are received and the handled ones get the shutdown timeout to finish
+pub async fn listen_for_message<'a, B, F, Fut>(
+    channel: &str,
+    subscription: &mut B::Subscription,
+    handler: F,
+    client: &'a B,
+    mut shutdown: Shutdown,
+) where
+    B: MessageBroker,
+    F: Fn(IncomingMessage, &'a B) -> Fut + 'a,
+    Fut: std::future::Future<Output = anyhow::Result<()>> + 'a,
+{
+    let settings = config().channels.get(channel);
+    let retry = settings.map(|settings| settings.retry.clone()).unwrap_or_default();
+    let max_in_flight = settings.map_or(1, |settings| settings.max_in_flight);
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            // messages are only received below the limit, the others wait at the broker
+            received = client.next(subscription), if in_flight.len() < max_in_flight => match received {
+                Some((message, delivery)) => {
+                    count(channel, Counter::Received);
+                    in_flight.push(handle(channel, &retry, message, delivery, &handler, client))
+                }
+                None => break,
+            },
+            Some(()) = in_flight.next(), if !in_flight.is_empty() => (),
+            _ = shutdown.triggered() => {
+                set_listening(channel, false);
+                if let Err(e) = client.unsubscribe(subscription).await {
+                    error!("Failed to unsubscribe from {}: {}", channel, e);
+                }
+                let timeout = shutdown.timeout();
+                let finished = tokio::time::timeout(timeout, async { while in_flight.next().await.is_some() {} }).await;
+                if finished.is_err() {
+                    // unsettled messages are delivered again by brokers with acknowledgements
+                    warn!("{} messages of {} were not handled within {:?}", in_flight.len(), channel, timeout);
+                }
+                return;
+            }
+        }
+    }
+    // the subscription ended, finish the messages that are still handled
+    error!("Subscription of {} ended", channel);
+    set_listening(channel, false);
+    while in_flight.next().await.is_some() {}
+}
+
+/// handles a message with retries and settles its delivery, in the trace of its producer and with its context in the logs
+async fn handle<'a, B, F, Fut>(
+    channel: &str,
+    retry: &RetryPolicy,
+    message: IncomingMessage,
+    delivery: B::Delivery,
+    handler: &F,
+    client: &'a B,
+) where
+    B: MessageBroker,
+    F: Fn(IncomingMessage, &'a B) -> Fut + 'a,
+    Fut: std::future::Future<Output = anyhow::Result<()>> + 'a,
+{
+    let _handling = Handling::start(channel);
+    // the spans of the handler continue the trace of the producer
+    let parent = extract_context(message.headers.as_ref());
+    let span = global::tracer("listener").start_with_context(format!("{} process", channel), &parent);
+    let context = parent.with_span(span);
+    let message_context = MessageContext::new(channel, &message, &context);
+    let subject = message.subject.clone();
+    let handled = async {
+        let settlement = handle_with_retries(channel, retry, message, &delivery, handler, client).await;
+        count(
+            channel,
+            match settlement {
+                Settlement::Ack => Counter::Processed,
+                _ => Counter::Failed,
+            },
+        );
+        if let Err(e) = client.ack(delivery, settlement).await {
+            error!("Failed to acknowledge message on {}: {}", subject, e);
+        }
+    };
+    with_message_context(message_context, handled.with_context(context)).await
+}
+
+async fn handle_with_retries<'a, B, F, Fut>(
+    channel: &str,
+    retry: &RetryPolicy,
+    message: IncomingMessage,
+    delivery: &B::Delivery,
+    handler: &F,
+    client: &'a B,
+) -> Settlement
+where
+    B: MessageBroker,
+    F: Fn(IncomingMessage, &'a B) -> Fut + 'a,
+    Fut: std::future::Future<Output = anyhow::Result<()>> + 'a,
+{
+    let mut attempt = 1;
+    let error = loop {
+        let started = Instant::now();
+        let result = handler(message.clone(), client).await;
+        observe_handler_duration(channel, started.elapsed());
+        match result {
+            Ok(()) => return Settlement::Ack,
+            // the policy denial was already routed and logged
+            Err(e) if e.is::<PolicyDenied>() => return Settlement::Term,
+            Err(e) if e.is::<Rejected>() || attempt >= retry.max_attempts => break e,
+            Err(e) => {
+                let delay = retry.delay(attempt);
+                warn!(
+                    "Attempt {} of {} to handle message on {} failed, retrying in {:?}: {}",
+                    attempt, retry.max_attempts, message.subject, delay, e
+                );
+                if let Err(e) = client.in_progress(delivery).await {
+                    warn!("Failed to extend the delivery of message on {}: {}", message.subject, e);
+                }
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    };
+    error!(
+        "Failed to handle message on {} after {} attempts: {}\nPayload: {}",
+        message.subject,
+        attempt,
+        error,
+        redacted_payload(channel, &message.payload)
+    );
+    match &retry.dead_letter_subject {
+        Some(dead_letter_subject) => {
+            match dead_letter(channel, dead_letter_subject, &message, &error, attempt, client).await {
+                Ok(()) => Settlement::Term,
+                Err(e) => {
+                    error!(
+                        "Failed to publish message on {} to {}: {}",
+                        message.subject, dead_letter_subject, e
+                    );
+                    Settlement::Nak
+                }
+            }
+        }
+        None if error.is::<Rejected>() => Settlement::Term,
+        None => Settlement::Nak,
+    }
+}
+
+/// republishes the failed `message` on `dead_letter_subject` with its headers and the failure in `x-dead-letter-*` headers
+async fn dead_letter<B: MessageBroker>(
+    channel: &str,
+    dead_letter_subject: &str,
+    message: &IncomingMessage,
+    error: &anyhow::Error,
+    attempts: u32,
+    client: &B,
+) -> anyhow::Result<()> {
+    let mut headers = message.headers.clone().unwrap_or_default();
+    // header values are single lines
+    let reason = error.to_string().replace(['\r', '\n'], " ");
+    let failed_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string();
+    headers.insert("x-dead-letter-reason", reason.as_str());
+    headers.insert("x-dead-letter-channel", channel);
+    headers.insert("x-dead-letter-subject", message.subject.as_str());
+    headers.insert("x-dead-letter-attempts", attempts.to_string().as_str());
+    headers.insert(
+        "x-dead-letter-rejected",
+        if error.is::<Rejected>() { "true" } else { "false" },
+    );
+    headers.insert("x-dead-letter-timestamp", failed_at.as_str());
+    client.publish("", dead_letter_subject, headers, &message.payload).await
+}
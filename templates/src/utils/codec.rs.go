@@ -0,0 +1,117 @@
+use serde::Serialize;
+use std::path::Path;
+{{ range .content_types }}
+    {{ if eq . "application/vnd.apache.avro" }}
+        use lazy_static::lazy_static;
+        use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+        lazy_static! {
+            // parsed avro schemas by schema file, so every schema is only parsed once
+            static ref AVRO_SCHEMAS: Mutex<HashMap<PathBuf, apache_avro::Schema>> = Mutex::new(HashMap::new());
+        }
+
+        fn avro_schema(schema_path: &Path) -> Result<apache_avro::Schema, String> {
+            let mut schemas = AVRO_SCHEMAS.lock().map_err(|e| e.to_string())?;
+            if let Some(schema) = schemas.get(schema_path) {
+                return Ok(schema.clone());
+            }
+            let schema_source = std::fs::read_to_string(schema_path)
+                .map_err(|e| format!("Failed to read schema file in path {}: {}", schema_path.display(), e))?;
+            let schema = apache_avro::Schema::parse_str(&schema_source).map_err(|e| e.to_string())?;
+            schemas.insert(schema_path.to_path_buf(), schema.clone());
+            Ok(schema)
+        }
+    {{ end }}
+{{ end }}
+
+/// decodes a message payload with the codec matching its `content_type`
+/// the result is a json value, so it can be validated against the payload schema before deserializing it
+/// `schema_path` points to the payload schema, which schema based codecs like avro need to read the payload
+#[allow(unused_variables)]
+pub fn decode_payload(content_type: &str, schema_path: &Path, payload: &[u8]) -> Result<serde_json::Value, String> {
+    match content_type {
+        "application/json" => serde_json::from_slice(payload).map_err(|e| e.to_string()),
+        {{ range .content_types }}
+            {{ if eq . "application/msgpack" }}
+                "application/msgpack" => rmp_serde::from_slice(payload).map_err(|e| e.to_string()),
+            {{ else if eq . "application/cbor" }}
+                "application/cbor" => ciborium::de::from_reader(payload).map_err(|e| e.to_string()),
+            {{ else if eq . "application/vnd.apache.avro" }}
+                "application/vnd.apache.avro" => {
+                    let schema = avro_schema(schema_path)?;
+                    let value = apache_avro::from_avro_datum(&schema, &mut &payload[..], None).map_err(|e| e.to_string())?;
+                    serde_json::Value::try_from(value).map_err(|e| e.to_string())
+                }
+            {{ end }}
+        {{ end }}
+        "text/plain" => match std::str::from_utf8(payload) {
+            Ok(text) => Ok(serde_json::Value::String(text.to_string())),
+            Err(e) => Err(e.to_string()),
+        },
+        // raw bytes are represented as an array of numbers, which deserializes into a `Vec<u8>`
+        "application/octet-stream" => Ok(serde_json::Value::Array(
+            payload.iter().map(|byte| serde_json::Value::from(*byte)).collect(),
+        )),
+        _ => Err(format!("Unsupported content type: {}", content_type)),
+    }
+}
+
+/// encodes a message payload with the codec matching its `content_type`
+#[allow(unused_variables)]
+pub fn encode_payload(content_type: &str, schema_path: &Path, payload: &impl Serialize) -> Result<Vec<u8>, String> {
+    match content_type {
+        "application/json" => serde_json::to_vec(payload).map_err(|e| e.to_string()),
+        {{ range .content_types }}
+            {{ if eq . "application/msgpack" }}
+                "application/msgpack" => rmp_serde::to_vec_named(payload).map_err(|e| e.to_string()),
+            {{ else if eq . "application/cbor" }}
+                "application/cbor" => {
+                    let mut buffer = Vec::new();
+                    ciborium::ser::into_writer(payload, &mut buffer).map_err(|e| e.to_string())?;
+                    Ok(buffer)
+                }
+            {{ else if eq . "application/vnd.apache.avro" }}
+                "application/vnd.apache.avro" => {
+                    let schema = avro_schema(schema_path)?;
+                    let value = apache_avro::to_value(payload)
+                        .and_then(|value| value.resolve(&schema))
+                        .map_err(|e| e.to_string())?;
+                    apache_avro::to_avro_datum(&schema, value).map_err(|e| e.to_string())
+                }
+            {{ end }}
+        {{ end }}
+        "text/plain" => match serde_json::to_value(payload).map_err(|e| e.to_string())? {
+            serde_json::Value::String(text) => Ok(text.into_bytes()),
+            value => Ok(value.to_string().into_bytes()),
+        },
+        "application/octet-stream" => match serde_json::to_value(payload).map_err(|e| e.to_string())? {
+            serde_json::Value::Array(bytes) => bytes
+                .iter()
+                .map(|byte| match byte.as_u64() {
+                    Some(byte) if byte <= u8::MAX as u64 => Ok(byte as u8),
+                    _ => Err(format!("{} is not a byte", byte)),
+                })
+                .collect(),
+            value => Err(format!("{} is not a byte array", value)),
+        },
+        _ => Err(format!("Unsupported content type: {}", content_type)),
+    }
+}
+{{ range .content_types }}
+    {{ if eq . "application/x-protobuf" }}
+
+        /// decodes a protobuf payload into the prost message `T`
+        /// the result is a json value like for the other codecs, so the handlers can treat all payloads the same way
+        pub fn decode_protobuf_payload<T: prost::Message + Default + Serialize>(payload: &[u8]) -> Result<serde_json::Value, String> {
+            let message = T::decode(payload).map_err(|e| e.to_string())?;
+            serde_json::to_value(message).map_err(|e| e.to_string())
+        }
+
+        /// encodes a payload as the prost message `T`, the payload may also be the json value of a `T`
+        pub fn encode_protobuf_payload<T: prost::Message + serde::de::DeserializeOwned>(payload: &impl Serialize) -> Result<Vec<u8>, String> {
+            let value = serde_json::to_value(payload).map_err(|e| e.to_string())?;
+            let message = serde_json::from_value::<T>(value).map_err(|e| e.to_string())?;
+            Ok(message.encode_to_vec())
+        }
+    {{ end }}
+{{ end }}
@@ -1,24 +1,296 @@
-use async_nats::{Client, Message, Subscriber};
+{{ if eq .protocol "nats" }}
+pub use async_nats::HeaderMap;
+use super::{consume, IncomingMessage, MessageBroker, Settlement, StreamMessages};
+use anyhow::anyhow;
+use async_nats::jetstream::{self, AckKind};
+use async_nats::rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use async_nats::rustls::{self, Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use async_nats::ConnectOptions;
+use async_trait::async_trait;
 use futures::StreamExt;
-use log::debug;
+use log::{debug, info};
+use rustls_pemfile::Item;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use crate::config::{config, get_secret, TlsConfig};
 
-pub async fn listen_for_message<'a, F, Fut>(sub: &mut Subscriber, handler: F, client: &'a Client)
-where
-    F: Fn(Message,&'a Client) -> Fut + 'a,
-    Fut: std::future::Future<Output = ()> + 'a,
-{
-    while let Some(message) = sub.next().await {
-        handler(message, client).await;
-        debug!("Message received by Subscriber: {:?}", sub);
+/// connection to the nats server, jetstream channels are consumed through it as well
+#[derive(Clone, Debug)]
+pub struct Client {
+    client: async_nats::Client,
+}
+
+/// a core nats subscription or the messages of a jetstream consumer
+pub enum Subscription {
+    Core(async_nats::Subscriber),
+    Stream(StreamMessages),
+}
+
+impl From<async_nats::Message> for IncomingMessage {
+    fn from(message: async_nats::Message) -> Self {
+        IncomingMessage {
+            subject: message.subject.to_string(),
+            reply: message.reply.map(|reply| reply.to_string()),
+            payload: message.payload.to_vec(),
+            headers: message.headers,
+        }
     }
 }
 
-pub async fn publish_message(client: &Client, channel: &str, payload: &str) {
-    let owned_payload = payload.to_owned().into(); // Convert to Bytes
-    client
-        .publish(channel.to_string(), owned_payload)
-        .await
-        .unwrap();
-    debug!("Published message to channel: {}", channel);
+/// how the service authenticates, the alternatives of the security requirements of the server
+const AUTH_METHODS: &[&str] = &[{{ range .auth_methods }}"{{ . }}", {{ end }}];
+
+/// connects to the nats server at `server_url`, authenticated as configured by `connect_options`
+/// `nats+tls://` urls connect with tls like `tls://` ones
+pub async fn connect(server_url: &str) -> Result<Client, async_nats::Error> {
+    let server_url = server_url.replacen("nats+tls://", "tls://", 1);
+    Ok(Client {
+        client: connect_options()?.connect(server_url).await?,
+    })
+}
+
+/// authenticates with the first of the security requirements of the server whose secrets are set,
+/// fails if the server requires authentication and none are set, so a misconfigured service stops right away
+pub fn connect_options() -> Result<ConnectOptions, async_nats::Error> {
+    let tls = &config().tls;
+    let options = tls_options(ConnectOptions::new(), tls)?;
+    if AUTH_METHODS.is_empty() {
+        return Ok(options);
+    }
+    for method in AUTH_METHODS {
+        let options = match *method {
+            "user_password" => match (get_secret("NATS_USER")?, get_secret("NATS_PASSWORD")?) {
+                (Some(user), Some(password)) => options.user_and_password(user, password),
+                _ => continue,
+            },
+            "token" => match get_secret("NATS_TOKEN")? {
+                Some(token) => options.token(token),
+                None => continue,
+            },
+            "nkey" => match get_secret("NATS_NKEY_SEED")? {
+                Some(seed) => options.nkey(seed),
+                None => continue,
+            },
+            // the content of a `.creds` file, or its path in NATS_CREDS_FILE
+            "creds" => match get_secret("NATS_CREDS")? {
+                Some(creds) => options.credentials(&creds)?,
+                None => continue,
+            },
+            // the client certificate of mutual tls identifies the service
+            "x509" => match tls.client_cert {
+                Some(_) => options,
+                None => continue,
+            },
+            _ => continue,
+        };
+        info!("Authenticating with {}", method);
+        return Ok(options);
+    }
+    let secrets: Vec<&str> = AUTH_METHODS
+        .iter()
+        .map(|method| match *method {
+            "user_password" => "NATS_USER and NATS_PASSWORD",
+            "token" => "NATS_TOKEN",
+            "nkey" => "NATS_NKEY_SEED",
+            "x509" => "TLS_CERT_FILE and TLS_KEY_FILE",
+            _ => "NATS_CREDS",
+        })
+        .collect();
+    Err(format!(
+        "The server requires authentication, set {} (or the *_FILE variables pointing to them)",
+        secrets.join(" or ")
+    )
+    .into())
 }
 
+/// applies the `TLS_*` settings, async-nats verifies the server certificate against the host of the server url,
+/// so another server name needs an own rustls config
+fn tls_options(options: ConnectOptions, tls: &TlsConfig) -> Result<ConnectOptions, async_nats::Error> {
+    let mut options = options.require_tls(tls.required);
+    if let Some(server_name) = &tls.server_name {
+        return Ok(options.tls_client_config(rustls_config(tls, server_name)?));
+    }
+    if let Some(ca_file) = &tls.ca_file {
+        options = options.add_root_certificates(ca_file.clone());
+    }
+    if let Some((cert, key)) = &tls.client_cert {
+        options = options.add_client_certificate(cert.clone(), key.clone());
+    }
+    Ok(options)
+}
+
+/// verifies the server certificate against `server_name` instead of the host connected to
+struct ServerNameVerifier {
+    server_name: ServerName,
+    verifier: WebPkiVerifier,
+}
+
+impl ServerCertVerifier for ServerNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.verifier
+            .verify_server_cert(end_entity, intermediates, &self.server_name, scts, ocsp_response, now)
+    }
+}
+
+/// the tls config async-nats would build from the `TLS_*` settings, verifying the server certificate against `server_name`
+fn rustls_config(tls: &TlsConfig, server_name: &str) -> Result<ClientConfig, async_nats::Error> {
+    let certificates = match &tls.ca_file {
+        Some(ca_file) => rustls_pemfile::certs(&mut read_pem(ca_file)?)?,
+        None => rustls_native_certs::load_native_certs()?
+            .into_iter()
+            .map(|certificate| certificate.0)
+            .collect(),
+    };
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(&certificates);
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(ServerNameVerifier {
+            server_name: ServerName::try_from(server_name)?,
+            verifier: WebPkiVerifier::new(roots, None),
+        }));
+    match &tls.client_cert {
+        Some((cert, key)) => {
+            let chain = rustls_pemfile::certs(&mut read_pem(cert)?)?
+                .into_iter()
+                .map(Certificate)
+                .collect();
+            let key = rustls_pemfile::read_all(&mut read_pem(key)?)?
+                .into_iter()
+                .find_map(|item| match item {
+                    Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+                    _ => None,
+                })
+                .ok_or(format!("No private key in {}", key.display()))?;
+            Ok(builder.with_client_auth_cert(chain, key)?)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn read_pem(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscription;
+    /// jetstream messages are acknowledged, core nats messages need no acknowledgement
+    type Delivery = Option<jetstream::Message>;
+
+    async fn publish(&self, _channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.client
+            .publish_with_headers(subject.to_string(), headers, payload.to_vec().into())
+            .await?;
+        debug!("Published message to subject: {}", subject);
+        Ok(())
+    }
+
+    /// channels with `{channel}_STREAM` are read by a durable jetstream consumer configured by the `{channel}_*` settings,
+    /// channels with `{channel}_QUEUE` join that queue group
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<Subscription> {
+        let settings = config().channels.get(channel);
+        if let Some((stream, settings)) = settings.and_then(|settings| Some((settings.stream.as_ref()?, settings))) {
+            let messages = consume(&self.client, channel, &settings.jetstream, stream, subject)
+                .await
+                .map_err(|e| anyhow!(e))?;
+            return Ok(Subscription::Stream(messages));
+        }
+        let subscriber = match settings.and_then(|settings| settings.queue.clone()) {
+            Some(queue) => self.client.queue_subscribe(subject.to_string(), queue).await?,
+            None => self.client.subscribe(subject.to_string()).await?,
+        };
+        Ok(Subscription::Core(subscriber))
+    }
+
+    async fn next(&self, subscription: &mut Subscription) -> Option<(IncomingMessage, Self::Delivery)> {
+        match subscription {
+            Subscription::Core(subscriber) => subscriber.next().await.map(|message| (message.into(), None)),
+            Subscription::Stream(messages) => {
+                let message = messages.next().await?;
+                // the reply subject of jetstream messages acknowledges them, it is no reply subject of a requester
+                let incoming = IncomingMessage {
+                    reply: None,
+                    ..message.message.clone().into()
+                };
+                Some((incoming, Some(message)))
+            }
+        }
+    }
+
+    /// handled jetstream messages are acked, nacked ones are delivered again up to `{channel}_MAX_DELIVER` times
+    /// and terminated ones never again
+    async fn ack(&self, delivery: Self::Delivery, settlement: Settlement) -> anyhow::Result<()> {
+        if let Some(message) = delivery {
+            let acknowledged = match settlement {
+                Settlement::Ack => message.ack().await,
+                Settlement::Nak => message.ack_with(AckKind::Nak(None)).await,
+                Settlement::Term => message.ack_with(AckKind::Term).await,
+            };
+            acknowledged.map_err(|e| anyhow!(e))?;
+        }
+        Ok(())
+    }
+
+    /// restarts the ack wait of a jetstream message, so it is not delivered again while its retry waits for the backoff
+    async fn in_progress(&self, delivery: &Self::Delivery) -> anyhow::Result<()> {
+        if let Some(message) = delivery {
+            message.ack_with(AckKind::Progress).await.map_err(|e| anyhow!(e))?;
+        }
+        Ok(())
+    }
+
+    async fn request(&self, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        let timeout = config().request_timeout;
+        let request = self
+            .client
+            .request_with_headers(subject.to_string(), headers, payload.to_vec().into());
+        match tokio::time::timeout(timeout, request).await {
+            Ok(reply) => {
+                debug!("Received reply on subject: {}", subject);
+                Ok(reply?.into())
+            }
+            Err(_) => Err(anyhow!("Request on subject {} timed out after {:?}", subject, timeout)),
+        }
+    }
+
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.client
+            .publish_with_headers(reply_subject.to_string(), headers, payload.to_vec().into())
+            .await?;
+        debug!("Published reply to subject: {}", reply_subject);
+        Ok(())
+    }
+
+    /// core nats subscriptions are unsubscribed, jetstream consumers stop pulling once the messages are dropped
+    async fn unsubscribe(&self, subscription: &mut Subscription) -> anyhow::Result<()> {
+        if let Subscription::Core(subscriber) = subscription {
+            subscriber.unsubscribe().await?;
+        }
+        Ok(())
+    }
+
+    /// publishing only buffers the messages in the client
+    async fn flush(&self) -> anyhow::Result<()> {
+        self.client.flush().await?;
+        Ok(())
+    }
+
+    fn connected(&self) -> bool {
+        self.client.connection_state() == async_nats::connection::State::Connected
+    }
+}
+{{ end }}
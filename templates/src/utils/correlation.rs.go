@@ -0,0 +1,81 @@
+use super::HeaderMap;
+
+/// Where a correlation id is located, parsed from an AsyncAPI runtime expression
+/// e.g. `$message.header#/correlation_id` or `$message.payload#/sentAt`
+#[derive(Debug, PartialEq)]
+pub enum CorrelationIdLocation {
+    Header(String),
+    Payload(String),
+}
+
+impl CorrelationIdLocation {
+    pub fn parse(runtime_expression: &str) -> Option<Self> {
+        let (source, pointer) = runtime_expression.split_once('#')?;
+        match source {
+            "$message.header" => {
+                // headers are flat, so only the last token of the json pointer names the header
+                let header = pointer.rsplit('/').next()?.replace("~1", "/").replace("~0", "~");
+                Some(CorrelationIdLocation::Header(header))
+            }
+            "$message.payload" => Some(CorrelationIdLocation::Payload(pointer.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// reads the correlation id from the message headers or payload, depending on the `location` runtime expression
+pub fn extract_correlation_id(
+    location: &str,
+    headers: Option<&HeaderMap>,
+    payload: &serde_json::Value,
+) -> Option<String> {
+    match CorrelationIdLocation::parse(location)? {
+        CorrelationIdLocation::Header(header) => headers?
+            .get(header.as_str())
+            .map(|value| value.as_str().to_string()),
+        CorrelationIdLocation::Payload(pointer) => match payload.pointer(&pointer)? {
+            serde_json::Value::String(id) => Some(id.clone()),
+            id => Some(id.to_string()),
+        },
+    }
+}
+
+/// writes the correlation id to the location described by the `location` runtime expression,
+/// a missing payload field is created together with the objects leading to it
+pub fn insert_correlation_id(
+    location: &str,
+    headers: &mut HeaderMap,
+    payload: &mut serde_json::Value,
+    correlation_id: &str,
+) -> Result<(), String> {
+    match CorrelationIdLocation::parse(location) {
+        Some(CorrelationIdLocation::Header(header)) => {
+            headers.insert(header.as_str(), correlation_id);
+            Ok(())
+        }
+        Some(CorrelationIdLocation::Payload(pointer)) => {
+            let mut target = payload;
+            for token in pointer.split('/').skip(1) {
+                let token = token.replace("~1", "/").replace("~0", "~");
+                if target.is_null() {
+                    *target = serde_json::Value::Object(serde_json::Map::new());
+                }
+                target = match target {
+                    serde_json::Value::Object(fields) => fields.entry(token).or_insert(serde_json::Value::Null),
+                    serde_json::Value::Array(items) => match token.parse::<usize>().ok().and_then(|index| items.get_mut(index)) {
+                        Some(item) => item,
+                        None => return Err(format!("Cannot write the correlation id to {}, no item {}", location, token)),
+                    },
+                    _ => return Err(format!("Cannot write the correlation id to {}, {} is not an object", location, token)),
+                };
+            }
+            *target = serde_json::Value::String(correlation_id.to_string());
+            Ok(())
+        }
+        None => Err(format!("Invalid correlation id location {}", location)),
+    }
+}
+
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
@@ -0,0 +1,101 @@
+use super::{MessageBroker, Shutdown};
+use crate::config::config;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// whether the service takes messages, set once the channels are subscribed and unset again on shutdown
+static READY: AtomicBool = AtomicBool::new(false);
+/// whether the broker connection is up, checked every second while the service runs
+static CONNECTED: AtomicBool = AtomicBool::new(true);
+static NEXT_HANDLING_ID: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /// the subscribed channels and whether their subscription still delivers messages
+    static ref CHANNELS: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+    /// the channel and start of every message that is currently handled
+    static ref HANDLING: Mutex<HashMap<u64, (String, Instant)>> = Mutex::new(HashMap::new());
+}
+
+pub fn set_ready(ready: bool) {
+    READY.store(ready, Ordering::SeqCst);
+}
+
+/// records that `channel` was subscribed, the service is only ready while all subscribed channels listen
+pub fn set_listening(channel: &str, listening: bool) {
+    CHANNELS.lock().unwrap().insert(channel.to_string(), listening);
+}
+
+/// whether the broker connection was up at the last check
+pub fn broker_connected() -> bool {
+    CONNECTED.load(Ordering::SeqCst)
+}
+
+/// checks the broker connection every second until `shutdown` is triggered
+pub async fn watch_connection<B: MessageBroker>(client: &B, mut shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => CONNECTED.store(client.connected(), Ordering::SeqCst),
+            _ = shutdown.triggered() => return,
+        }
+    }
+}
+
+/// `Ok` if the service is ready to take messages, otherwise why it is not
+pub fn readiness() -> Result<(), String> {
+    if !READY.load(Ordering::SeqCst) {
+        return Err("not ready".to_string());
+    }
+    if !CONNECTED.load(Ordering::SeqCst) {
+        return Err("not connected to the broker".to_string());
+    }
+    let channels = CHANNELS.lock().unwrap();
+    let mut stopped: Vec<&str> = channels
+        .iter()
+        .filter(|(_, listening)| !**listening)
+        .map(|(channel, _)| channel.as_str())
+        .collect();
+    if !stopped.is_empty() {
+        stopped.sort();
+        return Err(format!("not listening on {}", stopped.join(", ")));
+    }
+    Ok(())
+}
+
+/// `Ok` unless a message has been handled for longer than `LIVENESS_HANDLER_TIMEOUT_MS` (300000),
+/// which means its handler is stuck and the service has to be restarted
+pub fn liveness() -> Result<(), String> {
+    let timeout = config().liveness_handler_timeout;
+    let handling = HANDLING.lock().unwrap();
+    match handling.values().find(|(_, started)| started.elapsed() > timeout) {
+        Some((channel, started)) => Err(format!(
+            "a message on {} has been handled for {:?}",
+            channel,
+            started.elapsed()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// tracks a message from the start of its handler until it is settled
+pub struct Handling(u64);
+
+impl Handling {
+    pub fn start(channel: &str) -> Self {
+        let id = NEXT_HANDLING_ID.fetch_add(1, Ordering::Relaxed);
+        HANDLING
+            .lock()
+            .unwrap()
+            .insert(id, (channel.to_string(), Instant::now()));
+        Handling(id)
+    }
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        HANDLING.lock().unwrap().remove(&self.0);
+    }
+}
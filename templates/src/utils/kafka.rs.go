@@ -0,0 +1,300 @@
+{{ if eq .protocol "kafka" }}
+use super::{IncomingMessage, MessageBroker, Settlement};
+use crate::config::{self, TlsConfig};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, Headers, OwnedHeaders};
+use rdkafka::mocking::MockCluster;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Message as KafkaMessage, Offset, TopicPartitionList};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// a consumer subscribed to the topic of one channel, offsets are committed manually
+#[derive(Clone)]
+pub struct Subscriber {
+    consumer: Arc<StreamConsumer>,
+    busy: Arc<Mutex<BusyPartitions>>,
+}
+
+/// the topics and partitions with a message that is not settled yet, they are paused until it is
+/// so only one message per partition is in flight and a nak can rewind the partition without receiving handled messages again
+type BusyPartitions = BTreeSet<(String, i32)>;
+
+/// the offset of a received message, committed by the consumer that received it
+pub struct Delivery {
+    subscriber: Subscriber,
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+/// message headers, kafka header values are bytes but only utf-8 values are kept
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap(BTreeMap<String, String>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+impl From<&BorrowedMessage<'_>> for IncomingMessage {
+    fn from(message: &BorrowedMessage<'_>) -> Self {
+        let headers = message.headers().map(|headers| {
+            let mut header_map = HeaderMap::new();
+            for header in headers.iter() {
+                if let Some(Ok(value)) = header.value.map(std::str::from_utf8) {
+                    header_map.insert(header.key, value);
+                }
+            }
+            header_map
+        });
+        IncomingMessage {
+            subject: message.topic().to_string(),
+            reply: None,
+            payload: message.payload().unwrap_or_default().to_vec(),
+            headers,
+        }
+    }
+}
+
+/// connection to the kafka cluster, producing is shared by all channels, consumers are created per channel
+#[derive(Clone)]
+pub struct Client {
+    /// the bootstrap servers and tls settings shared by the producer and the consumers
+    config: ClientConfig,
+    producer: FutureProducer,
+}
+
+/// connects to the comma separated `bootstrap_servers`, with tls if `TLS_REQUIRED` is set
+/// `mock` starts an in-process mock cluster instead, so the service can be tried without a broker
+pub fn connect(bootstrap_servers: &str) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
+    let mut config = ClientConfig::new();
+    match bootstrap_servers {
+        "mock" => {
+            // the mock cluster has to live as long as the service
+            let cluster = Box::leak(Box::new(MockCluster::new(1)?));
+            info!("Started kafka mock cluster on {}", cluster.bootstrap_servers());
+            config.set("bootstrap.servers", cluster.bootstrap_servers());
+        }
+        bootstrap_servers => {
+            config.set("bootstrap.servers", bootstrap_servers);
+            set_tls(&mut config, &config::config().tls);
+        }
+    };
+    let producer = config.clone().set("message.timeout.ms", "5000").create()?;
+    Ok(Client { config, producer })
+}
+
+/// librdkafka verifies the certificates of the brokers against the host names they advertise
+fn set_tls(config: &mut ClientConfig, tls: &TlsConfig) {
+    if !tls.required {
+        return;
+    }
+    config.set("security.protocol", "ssl");
+    if let Some(ca_file) = &tls.ca_file {
+        config.set("ssl.ca.location", ca_file.display().to_string());
+    }
+    if let Some((cert, key)) = &tls.client_cert {
+        config
+            .set("ssl.certificate.location", cert.display().to_string())
+            .set("ssl.key.location", key.display().to_string());
+    }
+    if let Some(server_name) = &tls.server_name {
+        warn!("TLS_SERVER_NAME {} is not supported by kafka, the advertised host names of the brokers are verified", server_name);
+    }
+}
+
+/// kafka subscribes to a regex if the topic starts with `^`, which is used for parameterized channels
+fn topic_subscription(topic: &str) -> String {
+    if !topic.contains('{') {
+        return topic.to_string();
+    }
+    let mut pattern = "^".to_string();
+    let mut in_parameter = false;
+    for c in topic.chars() {
+        match c {
+            '{' => in_parameter = true,
+            '}' => {
+                in_parameter = false;
+                pattern.push_str("[^.]+");
+            }
+            _ if in_parameter => (),
+            '.' | '-' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '$' | '^' | '|' | '\\' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            _ => pattern.push(c),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscriber;
+    type Delivery = Delivery;
+
+    /// publishes without key, so the messages are distributed over all partitions
+    async fn publish(&self, _channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        publish_keyed_message(self, subject, None, headers, payload).await
+    }
+
+    async fn publish_keyed(
+        &self,
+        _channel: &str,
+        subject: &str,
+        key: Option<&str>,
+        headers: HeaderMap,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        publish_keyed_message(self, subject, key, headers, payload).await
+    }
+
+    /// joins the consumer group `{channel}_GROUP_ID` (as `{channel}_CLIENT_ID`) and subscribes to `subject`
+    /// channel parameters like `{streetlightId}` match any topic segment
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<Subscriber> {
+        let settings = config::config().channels.get(channel);
+        let mut config = self.config.clone();
+        config
+            .set("group.id", settings.map_or(channel, |settings| &settings.group_id))
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", &config::config().kafka.auto_offset_reset);
+        if let Some(client_id) = settings.and_then(|settings| settings.client_id.as_ref()) {
+            config.set("client.id", client_id);
+        }
+        let consumer: StreamConsumer = config.create()?;
+        consumer.subscribe(&[&topic_subscription(subject)])?;
+        Ok(Subscriber {
+            consumer: Arc::new(consumer),
+            busy: Arc::default(),
+        })
+    }
+
+    async fn next(&self, subscription: &mut Subscriber) -> Option<(IncomingMessage, Delivery)> {
+        loop {
+            match subscription.consumer.recv().await {
+                Ok(message) => {
+                    let partition = (message.topic().to_string(), message.partition());
+                    // messages fetched before the partition was paused are received again once it is resumed
+                    if !subscription
+                        .busy
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .insert(partition)
+                    {
+                        continue;
+                    }
+                    let mut paused = TopicPartitionList::new();
+                    paused.add_partition(message.topic(), message.partition());
+                    if let Err(e) = subscription.consumer.pause(&paused) {
+                        warn!("Failed to pause topic {} partition {}: {}", message.topic(), message.partition(), e);
+                    }
+                    let delivery = Delivery {
+                        subscriber: subscription.clone(),
+                        topic: message.topic().to_string(),
+                        partition: message.partition(),
+                        offset: message.offset(),
+                    };
+                    return Some((IncomingMessage::from(&message), delivery));
+                }
+                Err(e) => error!("Failed to receive message: {}", e),
+            }
+        }
+    }
+
+    /// commits the offset of handled and terminated messages, a nacked message is consumed again by seeking back to its offset
+    /// the partition of the message is then resumed at the next message to handle
+    async fn ack(&self, delivery: Delivery, settlement: Settlement) -> anyhow::Result<()> {
+        let Delivery { subscriber, topic, partition, offset } = delivery;
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition(&topic, partition);
+        let settled = async {
+            let next = match settlement {
+                Settlement::Nak => offset,
+                _ => {
+                    let mut offsets = TopicPartitionList::new();
+                    offsets.add_partition_offset(&topic, partition, Offset::Offset(offset + 1))?;
+                    subscriber.consumer.commit(&offsets, CommitMode::Async)?;
+                    debug!("Committed offset {} of topic {} partition {}", offset, topic, partition);
+                    offset + 1
+                }
+            };
+            // skips the messages fetched while the partition was paused, they are fetched again from here
+            subscriber
+                .consumer
+                .seek(&topic, partition, Offset::Offset(next), Duration::from_secs(5))?;
+            anyhow::Ok(())
+        }
+        .await;
+        subscriber
+            .busy
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(topic.clone(), partition));
+        subscriber.consumer.resume(&partitions)?;
+        settled
+    }
+
+    /// kafka has no request/reply, requesters have to consume the reply channel themselves
+    async fn request(&self, subject: &str, _headers: HeaderMap, _payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        Err(anyhow!("Requests on topic {} are not supported by the kafka backend", subject))
+    }
+
+    /// replies are published to the reply channel, with the correlation id in the headers if it is located there
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        publish_keyed_message(self, reply_subject, None, headers, payload).await
+    }
+}
+
+/// publishes a message, messages with the same `key` end up in the same partition and keep their order
+pub async fn publish_keyed_message(
+    client: &Client,
+    channel: &str,
+    key: Option<&str>,
+    headers: HeaderMap,
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    let mut kafka_headers = OwnedHeaders::new();
+    for (header, value) in headers.0.iter() {
+        kafka_headers = kafka_headers.insert(Header {
+            key: header,
+            value: Some(value.as_str()),
+        });
+    }
+    let mut record = FutureRecord::<str, [u8]>::to(channel)
+        .payload(payload)
+        .headers(kafka_headers);
+    if let Some(key) = key {
+        record = record.key(key);
+    }
+    let (partition, offset) = client
+        .producer
+        .send(record, Duration::from_secs(0))
+        .await
+        .map_err(|(e, _)| e)?;
+    debug!(
+        "Published message to topic {} partition {} offset {}",
+        channel, partition, offset
+    );
+    Ok(())
+}
+{{ end }}
@@ -0,0 +1,177 @@
+use super::{HeaderMap, IncomingMessage, MessageBroker, Settlement};
+use crate::config::config;
+use async_trait::async_trait;
+use log::debug;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// receives the messages published on the subjects matching a subscription
+pub type MemorySubscription = mpsc::UnboundedReceiver<IncomingMessage>;
+
+struct Subscriber {
+    pattern: String,
+    /// subscribers of the same queue group share the messages, each message goes to one of them
+    queue: Option<String>,
+    sender: mpsc::UnboundedSender<IncomingMessage>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    subscribers: Vec<Subscriber>,
+    /// the member of each queue group that receives the next message
+    next_member: HashMap<(String, String), usize>,
+}
+
+/// an in-process broker, selected with `SERVER_URL = "memory"` and used by the tests, so the service runs without a broker
+/// subjects are split into tokens at `.` and `/`, `*`, `+` and channel parameters like `{userId}` match one token,
+/// `>` and `#` match the remaining tokens
+#[derive(Clone, Default)]
+pub struct MemoryBroker {
+    subscribers: Arc<Mutex<Subscribers>>,
+    next_inbox: Arc<AtomicU64>,
+}
+
+impl MemoryBroker {
+    pub fn new() -> Self {
+        MemoryBroker::default()
+    }
+
+    /// subscribes to `pattern`, messages are shared with the other subscribers of `queue`
+    pub fn subscribe_queue(&self, pattern: &str, queue: Option<String>) -> MemorySubscription {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        debug!("Subscribed to {} in memory", pattern);
+        self.subscribers.lock().unwrap().subscribers.push(Subscriber {
+            pattern: pattern.to_string(),
+            queue,
+            sender,
+        });
+        receiver
+    }
+
+    /// the number of subscriptions receiving the messages published on `subject`
+    pub fn subscribers(&self, subject: &str) -> usize {
+        let subscribers = self.subscribers.lock().unwrap();
+        subscribers
+            .subscribers
+            .iter()
+            .filter(|subscriber| !subscriber.sender.is_closed() && matches(&subscriber.pattern, subject))
+            .count()
+    }
+
+    /// delivers `message` to all matching subscribers without queue group and to one member of each matching queue group,
+    /// returns the number of subscribers that received it
+    fn deliver(&self, message: IncomingMessage) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        let mut groups: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        let mut receivers = 0;
+        for (index, subscriber) in subscribers.subscribers.iter().enumerate() {
+            if !matches(&subscriber.pattern, &message.subject) {
+                continue;
+            }
+            match &subscriber.queue {
+                Some(queue) => groups
+                    .entry((subscriber.pattern.clone(), queue.clone()))
+                    .or_default()
+                    .push(index),
+                None => {
+                    if subscriber.sender.send(message.clone()).is_ok() {
+                        receivers += 1;
+                    }
+                }
+            }
+        }
+        for (group, members) in groups {
+            let next_member = subscribers.next_member.entry(group).or_default();
+            let member = members[*next_member % members.len()];
+            *next_member += 1;
+            if subscribers.subscribers[member].sender.send(message.clone()).is_ok() {
+                receivers += 1;
+            }
+        }
+        receivers
+    }
+}
+
+/// whether the subject matches the subscribed pattern
+fn matches(pattern: &str, subject: &str) -> bool {
+    let mut subject = subject.split(['.', '/']);
+    for token in pattern.split(['.', '/']) {
+        match (token, subject.next()) {
+            (">" | "#", Some(_)) => return true,
+            ("*" | "+", Some(_)) => (),
+            (token, Some(_)) if token.starts_with('{') && token.ends_with('}') => (),
+            (token, Some(next)) if token == next => (),
+            _ => return false,
+        }
+    }
+    subject.next().is_none()
+}
+
+#[async_trait]
+impl MessageBroker for MemoryBroker {
+    type Subscription = MemorySubscription;
+    /// messages are handed over once, there is nothing to acknowledge
+    type Delivery = ();
+
+    async fn publish(&self, _channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        let receivers = self.deliver(IncomingMessage {
+            subject: subject.to_string(),
+            reply: None,
+            payload: payload.to_vec(),
+            headers: Some(headers),
+        });
+        debug!("Published message to {} subscribers of {} in memory", receivers, subject);
+        Ok(())
+    }
+
+    /// channels join their queue group, `{channel}_QUEUE`, or the consumer group of kafka and redis streams,
+    /// like the queues and consumer groups of the brokers
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<MemorySubscription> {
+        let queue = config().channels.get(channel).and_then(|settings| {
+            {{ if eq .protocol "kafka" }}
+            Some(settings.queue.clone().unwrap_or(settings.group_id.clone()))
+            {{ else if eq .protocol "redis" }}
+            Some(settings.queue.clone().unwrap_or(settings.group.clone()))
+            {{ else }}
+            settings.queue.clone()
+            {{ end }}
+        });
+        Ok(self.subscribe_queue(subject, queue))
+    }
+
+    async fn next(&self, subscription: &mut MemorySubscription) -> Option<(IncomingMessage, ())> {
+        subscription.recv().await.map(|message| (message, ()))
+    }
+
+    async fn ack(&self, _delivery: (), _settlement: Settlement) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// publishes the request with an inbox as reply subject, fails right away if nobody subscribed to `subject`
+    async fn request(&self, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        let timeout = config().request_timeout;
+        let inbox = format!("_INBOX.{}", self.next_inbox.fetch_add(1, Ordering::Relaxed));
+        let mut replies = self.subscribe_queue(&inbox, None);
+        let receivers = self.deliver(IncomingMessage {
+            subject: subject.to_string(),
+            reply: Some(inbox.clone()),
+            payload: payload.to_vec(),
+            headers: Some(headers),
+        });
+        if receivers == 0 {
+            return Err(anyhow::anyhow!("No subscribers for request on {}", subject));
+        }
+        match tokio::time::timeout(timeout, replies.recv()).await {
+            Ok(Some(reply)) => Ok(reply),
+            Ok(None) => Err(anyhow::anyhow!("Subscription of {} closed", inbox)),
+            Err(_) => Err(anyhow::anyhow!("Request on {} timed out after {:?}", subject, timeout)),
+        }
+    }
+
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.publish("", reply_subject, headers, payload).await
+    }
+}
@@ -0,0 +1,184 @@
+{{ if eq .protocol "mercure" }}
+use super::{IncomingMessage, MessageBroker, Settlement};
+use crate::config::{config, MercureJwt};
+use async_trait::async_trait;
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// receives the updates of one topic selector, the event stream is read by a background task
+pub type Subscriber = mpsc::UnboundedReceiver<IncomingMessage>;
+
+/// update fields besides the data, `id`, `type`, `retry` and `private` are sent to the hub when publishing
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap(BTreeMap<String, String>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// the hub all topics are published to and subscribed from
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    hub_url: String,
+    publisher_jwt: Option<String>,
+    subscriber_jwt: Option<String>,
+}
+
+/// the jwt of a role or a token signed (HS256) with its key, allowed to publish or subscribe to all topics
+fn jwt(settings: &MercureJwt, claim: &str) -> Result<Option<String>, jsonwebtoken::errors::Error> {
+    if let Some(token) = &settings.jwt {
+        return Ok(Some(token.clone()));
+    }
+    match &settings.jwt_key {
+        Some(key) => {
+            let claims = serde_json::json!({ "mercure": { claim: ["*"] } });
+            encode(&Header::default(), &claims, &EncodingKey::from_secret(key.as_bytes())).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// uses the hub at `hub_url` (e.g. `https://localhost/.well-known/mercure`)
+pub async fn connect(hub_url: &str) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
+    let publisher_jwt = jwt(&config().mercure.publisher, "publish")?;
+    if publisher_jwt.is_none() {
+        warn!("Neither MERCURE_PUBLISHER_JWT nor MERCURE_PUBLISHER_JWT_KEY is set, the hub will refuse updates");
+    }
+    Ok(Client {
+        http: reqwest::Client::new(),
+        hub_url: hub_url.to_string(),
+        publisher_jwt,
+        subscriber_jwt: jwt(&config().mercure.subscriber, "subscribe")?,
+    })
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscriber;
+    /// the hub keeps no delivery state, missed updates are resumed with `Last-Event-ID`
+    type Delivery = ();
+
+    /// posts an update of the topic `subject` to the hub, authorized by the publisher jwt
+    /// the `id`, `type`, `retry` and `private` headers are sent as fields of the update
+    async fn publish(&self, _channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        let data = String::from_utf8_lossy(payload).to_string();
+        let mut form = vec![("topic", subject.to_string()), ("data", data)];
+        for field in ["id", "type", "retry", "private"] {
+            if let Some(value) = headers.get(field) {
+                form.push((field, value.clone()));
+            }
+        }
+        let mut request = self.http.post(&self.hub_url).form(&form);
+        if let Some(token) = &self.publisher_jwt {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?.error_for_status()?;
+        debug!("Published update {} to topic {}", response.text().await.unwrap_or_default(), subject);
+        Ok(())
+    }
+
+    /// subscribes to the topic selector `subject`, uri templates like `https://example.com/books/{id}` match all their topics
+    /// the event stream is reopened after errors, resuming after the last received event with `Last-Event-ID`
+    async fn subscribe(&self, _channel: &str, subject: &str) -> anyhow::Result<Subscriber> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let client = self.clone();
+        let topic = subject.to_string();
+        let mut retry = config().mercure.retry;
+        tokio::spawn(async move {
+            let mut last_event_id: Option<String> = None;
+            loop {
+                let mut request = client
+                    .http
+                    .get(&client.hub_url)
+                    .query(&[("topic", &topic)])
+                    .header("Accept", "text/event-stream");
+                if let Some(token) = &client.subscriber_jwt {
+                    request = request.bearer_auth(token);
+                }
+                if let Some(id) = &last_event_id {
+                    request = request.header("Last-Event-ID", id);
+                }
+                let response = match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        error!("Failed to subscribe to {}, retrying in {:?}: {}", topic, retry, e);
+                        tokio::time::sleep(retry).await;
+                        continue;
+                    }
+                };
+                info!("Subscribed to {}", topic);
+                let mut events = response.bytes_stream().eventsource();
+                while let Some(event) = events.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            error!("Event stream of {} failed: {}", topic, e);
+                            break;
+                        }
+                    };
+                    if let Some(event_retry) = event.retry {
+                        retry = event_retry;
+                    }
+                    if !event.id.is_empty() {
+                        last_event_id = Some(event.id.clone());
+                    }
+                    let mut headers = HeaderMap::new();
+                    headers.insert("id", &event.id);
+                    headers.insert("type", &event.event);
+                    // mercure has no replies, replies are published to the reply channel
+                    let message = IncomingMessage {
+                        subject: topic.clone(),
+                        reply: None,
+                        payload: event.data.into_bytes(),
+                        headers: Some(headers),
+                    };
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+                warn!("Event stream of {} closed, resuming in {:?}", topic, retry);
+                tokio::time::sleep(retry).await;
+            }
+        });
+        Ok(receiver)
+    }
+
+    async fn next(&self, subscription: &mut Subscriber) -> Option<(IncomingMessage, ())> {
+        subscription.recv().await.map(|message| (message, ()))
+    }
+
+    async fn ack(&self, _delivery: (), _settlement: Settlement) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// mercure has no request/reply, requesters have to subscribe to the reply channel themselves
+    async fn request(&self, subject: &str, _headers: HeaderMap, _payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        Err(anyhow::anyhow!("Requests on topic {} are not supported by the mercure backend", subject))
+    }
+
+    /// replies are published as updates of the reply channel
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.publish("", reply_subject, headers, payload).await
+    }
+}
+{{ end }}
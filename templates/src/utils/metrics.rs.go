@@ -0,0 +1,171 @@
+use super::broker_connected;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// the channels messages are received on
+const CONSUMER_CHANNELS: &[&str] = &[{{ range .publish_channels }}"{{ (index . 1).unique_id }}", {{ end }}];
+/// the channels messages are published on
+const PRODUCER_CHANNELS: &[&str] = &[{{ range .subscribe_channels }}"{{ (index . 1).unique_id }}", {{ end }}];
+
+/// upper bounds of the handler duration buckets in seconds, the defaults of the prometheus clients
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// the per channel counters, exposed as `{channel}_{name}_total`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// messages received from the broker
+    Received,
+    /// messages handled and acked
+    Processed,
+    /// messages that failed every attempt or were rejected
+    Failed,
+    /// messages whose payload could not be decoded or did not match the schema
+    ValidationErrors,
+    /// messages denied by the OPA policy
+    PolicyDenials,
+    /// messages sent by the producers
+    Published,
+}
+
+impl Counter {
+    fn name(&self) -> &'static str {
+        match self {
+            Counter::Received => "messages_received",
+            Counter::Processed => "messages_processed",
+            Counter::Failed => "messages_failed",
+            Counter::ValidationErrors => "validation_errors",
+            Counter::PolicyDenials => "policy_denials",
+            Counter::Published => "messages_published",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Counter::Received => "Messages received",
+            Counter::Processed => "Messages handled successfully",
+            Counter::Failed => "Messages that failed every attempt or were rejected",
+            Counter::ValidationErrors => "Messages with a payload that could not be decoded or validated",
+            Counter::PolicyDenials => "Messages denied by the policy",
+            Counter::Published => "Messages published",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct ChannelMetrics {
+    counters: BTreeMap<Counter, u64>,
+    handler_duration: Histogram,
+    /// messages of the jetstream consumer not yet delivered
+    consumer_pending: Option<u64>,
+}
+
+lazy_static! {
+    static ref CHANNELS: Mutex<BTreeMap<String, ChannelMetrics>> = Mutex::new(BTreeMap::new());
+}
+
+/// increments `counter` of `channel`, messages on subjects outside the spec (empty `channel`) are not counted
+pub fn count(channel: &str, counter: Counter) {
+    if channel.is_empty() {
+        return;
+    }
+    let mut channels = CHANNELS.lock().unwrap();
+    *channels
+        .entry(channel.to_string())
+        .or_default()
+        .counters
+        .entry(counter)
+        .or_default() += 1;
+}
+
+/// records how long a handler call of `channel` took
+pub fn observe_handler_duration(channel: &str, duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let mut channels = CHANNELS.lock().unwrap();
+    let histogram = &mut channels.entry(channel.to_string()).or_default().handler_duration;
+    for (bucket, upper_bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+        if seconds <= upper_bound {
+            *bucket += 1;
+        }
+    }
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+/// records the number of messages the jetstream consumer of `channel` has not delivered yet
+pub fn set_consumer_pending(channel: &str, pending: u64) {
+    CHANNELS
+        .lock()
+        .unwrap()
+        .entry(channel.to_string())
+        .or_default()
+        .consumer_pending = Some(pending);
+}
+
+/// the metrics in the prometheus text format, every channel of the spec is listed even before its first message
+pub fn render_metrics() -> String {
+    let channels = CHANNELS.lock().unwrap();
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP broker_connected Whether the connection to the broker is up");
+    let _ = writeln!(out, "# TYPE broker_connected gauge");
+    let _ = writeln!(out, "broker_connected {}", broker_connected() as u8);
+    let empty = ChannelMetrics::default();
+    for channel in CONSUMER_CHANNELS {
+        let metrics = channels.get(*channel).unwrap_or(&empty);
+        for counter in [
+            Counter::Received,
+            Counter::Processed,
+            Counter::Failed,
+            Counter::ValidationErrors,
+            Counter::PolicyDenials,
+        ] {
+            write_counter(&mut out, channel, counter, metrics);
+        }
+        let name = format!("{}_handler_duration_seconds", channel);
+        let histogram = &metrics.handler_duration;
+        let _ = writeln!(out, "# HELP {} Duration of the handler calls", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bucket, upper_bound) in histogram.buckets.iter().zip(BUCKETS) {
+            write_bucket(&mut out, &name, &upper_bound.to_string(), *bucket);
+        }
+        write_bucket(&mut out, &name, "+Inf", histogram.count);
+        let _ = writeln!(out, "{}_sum {}", name, histogram.sum);
+        let _ = writeln!(out, "{}_count {}", name, histogram.count);
+        if let Some(pending) = metrics.consumer_pending {
+            let name = format!("{}_consumer_pending", channel);
+            let _ = writeln!(out, "# HELP {} Messages of the jetstream consumer not yet delivered", name);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, pending);
+        }
+    }
+    for channel in PRODUCER_CHANNELS {
+        write_counter(&mut out, channel, Counter::Published, channels.get(*channel).unwrap_or(&empty));
+    }
+    out
+}
+
+/// writes the bucket line without format braces, the escaped double braces would be taken as template actions
+fn write_bucket(out: &mut String, name: &str, upper_bound: &str, count: u64) {
+    out.push_str(name);
+    out.push_str("_bucket{le=\"");
+    out.push_str(upper_bound);
+    out.push_str("\"} ");
+    out.push_str(&count.to_string());
+    out.push('\n');
+}
+
+fn write_counter(out: &mut String, channel: &str, counter: Counter, metrics: &ChannelMetrics) {
+    let name = format!("{}_{}_total", channel, counter.name());
+    let _ = writeln!(out, "# HELP {} {}", name, counter.help());
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, metrics.counters.get(&counter).copied().unwrap_or(0));
+}
@@ -1,6 +1,40 @@
+{{ if eq .protocol "kafka" }}
+pub mod kafka;
+pub use kafka::*;
+{{ else if eq .protocol "mqtt" }}
+pub mod mqtt;
+pub use mqtt::*;
+{{ else if eq .protocol "amqp" }}
+pub mod amqp;
+pub use amqp::*;
+{{ else if eq .protocol "ws" }}
+pub mod ws;
+pub use ws::*;
+{{ else if eq .protocol "mercure" }}
+pub mod mercure;
+pub use mercure::*;
+{{ else if eq .protocol "redis" }}
+pub mod redis;
+pub use self::redis::*;
+{{ else }}
 pub mod common;
 pub use common::*;
 pub mod streams;
 pub use streams::*;
+{{ end }}
+pub mod broker;
+pub use broker::*;
+pub mod shutdown;
+pub use shutdown::*;
+pub mod health;
+pub use health::*;
+pub mod metrics;
+pub use metrics::*;
+pub mod memory;
+pub use memory::*;
 pub mod validator;
-pub use validator::*;
\ No newline at end of file
+pub use validator::*;
+pub mod correlation;
+pub use correlation::*;
+pub mod codec;
+pub use codec::*;
\ No newline at end of file
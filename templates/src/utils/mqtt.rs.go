@@ -0,0 +1,468 @@
+{{ if eq .protocol "mqtt" }}
+use super::{IncomingMessage, MessageBroker, Settlement};
+use crate::config::{config, TlsConfig};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+{{ if eq .protocol_version "5" }}
+use rumqttc::v5::mqttbytes::v5::{LastWill, Packet, Publish, PublishProperties};
+use rumqttc::v5::mqttbytes::{matches, QoS};
+use rumqttc::v5::{AsyncClient, Event, MqttOptions};
+{{ else }}
+use rumqttc::{matches, AsyncClient, Event, LastWill, MqttOptions, Packet, Publish, QoS};
+{{ end }}
+use rumqttc::tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rumqttc::tokio_rustls::rustls::client::WebPkiServerVerifier;
+use rumqttc::tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rumqttc::tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use rumqttc::{TlsConfiguration, Transport};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// receives the messages of one subscription, they are dispatched by the event loop of the client
+pub type Subscriber = mpsc::UnboundedReceiver<(IncomingMessage, Delivery)>;
+
+/// a received message to acknowledge, shared by the subscriptions whose filters match its topic
+#[derive(Clone)]
+pub struct Delivery(Arc<PendingAck>);
+
+struct PendingAck {
+    publish: Publish,
+    /// subscriptions that did not settle the message yet
+    unsettled: AtomicUsize,
+    /// set once a subscription nacked the message, it is then never acknowledged
+    nacked: AtomicBool,
+}
+
+impl Delivery {
+    fn new(publish: Publish, subscriptions: usize) -> Self {
+        Delivery(Arc::new(PendingAck {
+            publish,
+            unsettled: AtomicUsize::new(subscriptions),
+            nacked: AtomicBool::new(false),
+        }))
+    }
+
+    /// settles the message for one subscription, returns the message to acknowledge once all of them handled or terminated it
+    fn settle(&self, settlement: Settlement) -> Option<&Publish> {
+        if settlement == Settlement::Nak {
+            self.0.nacked.store(true, Ordering::SeqCst);
+        }
+        let last = self.0.unsettled.fetch_sub(1, Ordering::SeqCst) == 1;
+        (last && !self.0.nacked.load(Ordering::SeqCst)).then_some(&self.0.publish)
+    }
+}
+
+/// message headers, mqtt 5 user properties (mqtt 3.1.1 has no headers)
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap(BTreeMap<String, String>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// the response topic of mqtt 5 requests is the reply subject
+impl From<Publish> for IncomingMessage {
+    {{ if eq .protocol_version "5" }}
+    fn from(publish: Publish) -> Self {
+        let properties = publish.properties.unwrap_or_default();
+        let mut headers = HeaderMap::new();
+        for (key, value) in properties.user_properties.iter() {
+            headers.insert(key, value);
+        }
+        IncomingMessage {
+            subject: String::from_utf8_lossy(&publish.topic).to_string(),
+            reply: properties.response_topic,
+            payload: publish.payload.to_vec(),
+            headers: Some(headers),
+        }
+    }
+    {{ else }}
+    fn from(publish: Publish) -> Self {
+        IncomingMessage {
+            subject: publish.topic,
+            reply: None,
+            payload: publish.payload.to_vec(),
+            headers: None,
+        }
+    }
+    {{ end }}
+}
+
+type Subscriptions = Arc<Mutex<Vec<(String, QoS, mpsc::UnboundedSender<(IncomingMessage, Delivery)>)>>>;
+
+/// connection to the mqtt broker, shared by all channels
+#[derive(Clone)]
+pub struct Client {
+    client: AsyncClient,
+    client_id: String,
+    subscriptions: Subscriptions,
+    /// set by the event loop on every connect and connection failure
+    connected: Arc<AtomicBool>,
+}
+
+/// maps the `0`, `1` or `2` of the mqtt bindings to a quality of service
+pub fn qos(level: u8) -> QoS {
+    match level {
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        _ => QoS::AtMostOnce,
+    }
+}
+
+/// verifies the server certificate against `server_name` instead of the host connected to
+#[derive(Debug)]
+struct ServerNameVerifier {
+    server_name: ServerName<'static>,
+    verifier: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for ServerNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.verifier
+            .verify_server_cert(end_entity, intermediates, &self.server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+/// a tls config trusting `TLS_CA_FILE` or the system certificates, with the client certificate of `TLS_CERT_FILE`
+/// and verifying the server certificate against `TLS_SERVER_NAME` if set
+fn rustls_config(tls: &TlsConfig) -> Result<ClientConfig, Box<dyn std::error::Error + Send + Sync>> {
+    let certificates = match &tls.ca_file {
+        Some(ca_file) => rustls_pemfile::certs(&mut read_pem(ca_file)?).collect::<Result<Vec<_>, _>>()?,
+        None => rustls_native_certs::load_native_certs()?,
+    };
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(certificates);
+    let verifier = WebPkiServerVerifier::builder(Arc::new(roots)).build()?;
+    let builder = match &tls.server_name {
+        Some(server_name) => ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(ServerNameVerifier {
+                server_name: ServerName::try_from(server_name.clone())?,
+                verifier,
+            })),
+        None => ClientConfig::builder().with_webpki_verifier(verifier),
+    };
+    match &tls.client_cert {
+        Some((cert, key)) => {
+            let chain = rustls_pemfile::certs(&mut read_pem(cert)?).collect::<Result<Vec<_>, _>>()?;
+            let key = rustls_pemfile::private_key(&mut read_pem(key)?)?
+                .ok_or(format!("No private key in {}", key.display()))?;
+            Ok(builder.with_client_auth_cert(chain, key)?)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn read_pem(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// qos and retain flag of a channel, configured by `{channel}_QOS` and `{channel}_RETAIN`
+pub fn channel_options(channel: &str) -> (QoS, bool) {
+    match config().channels.get(channel) {
+        Some(settings) => (qos(settings.qos), settings.retain),
+        None => (QoS::AtMostOnce, false),
+    }
+}
+
+/// connects to the broker at `server_url` (e.g. `mqtt://localhost:1883`) with the settings of the mqtt server binding
+/// `mqtts://` and `ssl://` urls connect with tls, like all urls if `TLS_REQUIRED` is set
+/// the event loop runs in the background, reconnects and subscribes again if the broker lost the session
+pub async fn connect(server_url: &str) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
+    let settings = &config().mqtt;
+    let tls = &config().tls;
+    let use_tls = tls.required || server_url.starts_with("mqtts://") || server_url.starts_with("ssl://");
+    let address = server_url
+        .split("://")
+        .last()
+        .unwrap_or(server_url)
+        .trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse()?),
+        None if use_tls => (address, 8883),
+        None => (address, 1883),
+    };
+    let client_id = settings.client_id.clone();
+    let mut options = MqttOptions::new(client_id.clone(), host, port);
+    if let Some(keep_alive) = settings.keep_alive {
+        options.set_keep_alive(keep_alive);
+    }
+    if let Some(clean_session) = settings.clean_session {
+        {{ if eq .protocol_version "5" }}
+        options.set_clean_start(clean_session);
+        {{ else }}
+        options.set_clean_session(clean_session);
+        {{ end }}
+    }
+    if let Some(last_will) = &settings.last_will {
+        let will = LastWill::new(
+            &last_will.topic,
+            last_will.message.clone(),
+            qos(last_will.qos),
+            last_will.retain,
+            {{ if eq .protocol_version "5" }}None,{{ end }}
+        );
+        options.set_last_will(will);
+    }
+    // messages are acknowledged once their handlers are done, see `MessageBroker::ack`
+    options.set_manual_acks(true);
+    if use_tls {
+        options.set_transport(Transport::tls_with_config(TlsConfiguration::Rustls(Arc::new(rustls_config(tls)?))));
+    }
+
+    let (client, mut event_loop) = AsyncClient::new(options, 100);
+    let subscriptions: Subscriptions = Arc::new(Mutex::new(vec![]));
+    let dispatch_client = client.clone();
+    let dispatch_subscriptions = subscriptions.clone();
+    let connected = Arc::new(AtomicBool::new(false));
+    let dispatch_connected = connected.clone();
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let message = IncomingMessage::from(publish.clone());
+                    let subscriptions = dispatch_subscriptions.lock().unwrap();
+                    let subscribers: Vec<_> = subscriptions
+                        .iter()
+                        .filter(|(filter, _, _)| matches(&message.subject, filter))
+                        .collect();
+                    // messages nobody subscribes to anymore, like late replies, are acknowledged right away
+                    if subscribers.is_empty() {
+                        if let Err(e) = dispatch_client.try_ack(&publish) {
+                            error!("Failed to acknowledge message on {}: {}", message.subject, e);
+                        }
+                    }
+                    let delivery = Delivery::new(publish, subscribers.len());
+                    for (filter, _, sender) in subscribers {
+                        if sender.send((message.clone(), delivery.clone())).is_err() {
+                            warn!("Subscriber of {} is gone", filter);
+                            if let Some(publish) = delivery.settle(Settlement::Ack) {
+                                if let Err(e) = dispatch_client.try_ack(publish) {
+                                    error!("Failed to acknowledge message on {}: {}", message.subject, e);
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::ConnAck(connack))) => {
+                    info!("Connected to mqtt broker");
+                    dispatch_connected.store(true, Ordering::SeqCst);
+                    if !connack.session_present {
+                        let filters: Vec<(String, QoS)> = dispatch_subscriptions
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .map(|(filter, qos, _)| (filter.clone(), *qos))
+                            .collect();
+                        // the requests wait for room in the request channel, which only this loop drains
+                        let client = dispatch_client.clone();
+                        tokio::spawn(async move {
+                            for (filter, qos) in filters {
+                                if let Err(e) = client.subscribe(filter.clone(), qos).await {
+                                    error!("Failed to subscribe to {} again: {}", filter, e);
+                                }
+                            }
+                        });
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    // the next poll reconnects
+                    error!("Connection to mqtt broker failed: {}", e);
+                    dispatch_connected.store(false, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+    Ok(Client {
+        client,
+        client_id,
+        subscriptions,
+        connected,
+    })
+}
+
+impl Client {
+    /// subscribes to `topic`, channel parameters like `{streetlightId}` are subscribed as `+` wildcards
+    async fn subscribe_topic(&self, topic: &str, qos: QoS) -> anyhow::Result<Subscriber> {
+        let filter = topic_filter(topic);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .push((filter.clone(), qos, sender));
+        self.client.subscribe(filter.clone(), qos).await?;
+        debug!("Subscribed to {} with {:?}", filter, qos);
+        Ok(receiver)
+    }
+}
+
+/// replaces `{parameter}` segments of a channel name with the single level wildcard `+`
+fn topic_filter(topic: &str) -> String {
+    topic
+        .split('/')
+        .map(|segment| match segment.starts_with('{') && segment.ends_with('}') {
+            true => "+",
+            false => segment,
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscriber;
+    /// the received publish packet, acknowledged (puback or pubrec) once handled
+    type Delivery = Delivery;
+
+    /// publishes with the qos and retain flag of `channel`, `headers` are sent as user properties with mqtt 5 and dropped with mqtt 3.1.1
+    async fn publish(&self, channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        let (qos, retain) = channel_options(channel);
+        publish_with_options(self, subject, qos, retain, headers, payload).await
+    }
+
+    /// subscribes to `subject` with the quality of service of `channel`
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<Subscriber> {
+        self.subscribe_topic(subject, channel_options(channel).0).await
+    }
+
+    async fn next(&self, subscription: &mut Subscriber) -> Option<(IncomingMessage, Delivery)> {
+        subscription.recv().await
+    }
+
+    /// handled and terminated qos 1 and 2 messages are acknowledged, nacked ones are left unacknowledged
+    /// and delivered again by the broker when a session that is not clean reconnects
+    async fn ack(&self, delivery: Delivery, settlement: Settlement) -> anyhow::Result<()> {
+        if let Some(publish) = delivery.settle(settlement) {
+            self.client.ack(publish).await?;
+        }
+        Ok(())
+    }
+
+    {{ if eq .protocol_version "5" }}
+    /// sends a request with a response topic and waits for the reply, fails if no reply arrives within `REQUEST_TIMEOUT_MS`
+    async fn request(&self, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        let timeout = crate::config::config().request_timeout;
+        let response_topic = format!("{}/replies/{}", self.client_id, uuid::Uuid::new_v4());
+        let mut replies = self.subscribe_topic(&response_topic, QoS::AtLeastOnce).await?;
+        let properties = PublishProperties {
+            response_topic: Some(response_topic.clone()),
+            user_properties: headers.0.into_iter().collect(),
+            ..Default::default()
+        };
+        self.client
+            .publish_with_properties(subject, QoS::AtLeastOnce, false, payload.to_vec(), properties)
+            .await?;
+        let reply = tokio::time::timeout(timeout, replies.recv()).await;
+        self.client.unsubscribe(response_topic.clone()).await?;
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .retain(|(filter, _, _)| filter != &response_topic);
+        match reply {
+            Ok(Some((reply, delivery))) => {
+                debug!("Received reply on topic: {}", response_topic);
+                self.ack(delivery, Settlement::Ack).await?;
+                Ok(reply)
+            }
+            Ok(None) => Err(anyhow!("Subscription of {} closed", response_topic)),
+            Err(_) => Err(anyhow!("Request on topic {} timed out after {:?}", subject, timeout)),
+        }
+    }
+    {{ else }}
+    /// mqtt 3.1.1 has no response topics, requesters have to subscribe to the reply channel themselves
+    async fn request(&self, subject: &str, _headers: HeaderMap, _payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        Err(anyhow!("Requests on topic {} need mqtt 5", subject))
+    }
+    {{ end }}
+
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        publish_with_options(self, reply_subject, QoS::AtLeastOnce, false, headers, payload).await
+    }
+
+    fn connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
+
+/// publishes a message, `headers` are sent as user properties with mqtt 5 and dropped with mqtt 3.1.1
+pub async fn publish_with_options(
+    client: &Client,
+    channel: &str,
+    qos: QoS,
+    retain: bool,
+    headers: HeaderMap,
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    {{ if eq .protocol_version "5" }}
+    let properties = PublishProperties {
+        user_properties: headers.0.into_iter().collect(),
+        ..Default::default()
+    };
+    client
+        .client
+        .publish_with_properties(channel, qos, retain, payload.to_vec(), properties)
+        .await?;
+    {{ else }}
+    if !headers.0.is_empty() {
+        debug!("Dropping headers of message to {}, mqtt 3.1.1 has no headers", channel);
+    }
+    client.client.publish(channel, qos, retain, payload.to_vec()).await?;
+    {{ end }}
+    debug!("Published message to topic: {}", channel);
+    Ok(())
+}
+{{ end }}
@@ -0,0 +1,462 @@
+{{ if eq .protocol "redis" }}
+use super::{IncomingMessage, MessageBroker, Settlement};
+use crate::config::{config, ChannelConfig};
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use redis::aio::MultiplexedConnection;
+use redis::streams::{
+    StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamMaxlen, StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, RedisResult};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, OnceCell};
+
+/// field of a stream entry holding the payload, the other fields are passed as headers
+const PAYLOAD_FIELD: &str = "payload";
+/// field of a stream entry naming the pub/sub channel the reply to a request is published on
+const REPLY_FIELD: &str = "reply_to";
+/// entries read from a stream at once, also the number of messages buffered for a handler
+const READ_COUNT: usize = 10;
+/// milliseconds a stream read waits for new entries, pending entries are reclaimed in between
+const BLOCK_MS: usize = 5000;
+
+/// receives the messages of one channel, they are read by a background task
+pub type Subscriber = mpsc::Receiver<(IncomingMessage, Option<Entry>)>;
+
+/// fields of stream entries besides the payload, pub/sub messages have no headers
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap(BTreeMap<String, String>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// a stream entry read by a consumer group, acknowledged once it was handled
+#[derive(Clone, Debug)]
+pub struct Entry {
+    stream: String,
+    group: String,
+    id: String,
+}
+
+impl Entry {
+    /// the message of a stream entry, the `reply_to` field is the reply subject and the fields besides the payload are the headers
+    fn read(stream: &str, group: &str, entry: StreamId) -> (IncomingMessage, Option<Entry>) {
+        let mut headers = HeaderMap::new();
+        let mut payload = vec![];
+        let mut reply = None;
+        for (field, value) in entry.map {
+            let value: Vec<u8> = redis::from_redis_value(&value).unwrap_or_default();
+            match field.as_str() {
+                PAYLOAD_FIELD => payload = value,
+                REPLY_FIELD => reply = Some(String::from_utf8_lossy(&value).to_string()),
+                _ => headers.insert(&field, &String::from_utf8_lossy(&value)),
+            }
+        }
+        let message = IncomingMessage {
+            subject: stream.to_string(),
+            reply,
+            payload,
+            headers: Some(headers),
+        };
+        let entry = Entry {
+            stream: stream.to_string(),
+            group: group.to_string(),
+            id: entry.id,
+        };
+        (message, Some(entry))
+    }
+}
+
+/// connection to the redis server, shared by the producers of all channels
+/// subscriptions use connections of their own, as pub/sub and blocking stream reads occupy a connection
+#[derive(Clone)]
+pub struct Client {
+    client: redis::Client,
+    connection: MultiplexedConnection,
+    /// subjects of the stream channels with the length the streams are trimmed to
+    streams: Arc<HashMap<String, Option<usize>>>,
+    /// reply channels of the requests, subscribed with the first request
+    replies: Arc<OnceCell<Replies>>,
+}
+
+/// pending requests by reply channel, the channels `{inbox}:{request id}` are subscribed as one pattern
+struct Replies {
+    inbox: String,
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<IncomingMessage>>>>,
+}
+
+/// removes a request from the pending requests once it got its reply, timed out or was cancelled
+struct PendingReply<'a> {
+    channel: String,
+    replies: &'a Replies,
+}
+
+impl Drop for PendingReply<'_> {
+    fn drop(&mut self) {
+        self.replies.pending.lock().unwrap().remove(&self.channel);
+    }
+}
+
+/// consumer group settings of a stream channel, see `ChannelConfig`
+struct Consumer {
+    group: String,
+    name: String,
+    min_idle_time: usize,
+}
+
+impl Consumer {
+    fn of(settings: &ChannelConfig) -> Self {
+        Consumer {
+            group: settings.group.clone(),
+            name: settings.consumer.clone(),
+            min_idle_time: settings.min_idle_time.as_millis() as usize,
+        }
+    }
+}
+
+/// the stream channels of the spec with the `maxLen` of their redis channel binding
+const STREAMS: &[(&str, Option<usize>)] = &[
+{{ range .subscribe_channels }}{{ if (index . 1).redis_stream }}    ("{{ (index . 1).unique_id }}", {{ if key_exists (index . 1) "channel_bindings" "redis" "maxLen" }}Some({{ (index . 1).channel_bindings.redis.maxLen }}){{ else }}None{{ end }}),
+{{ end }}{{ end }}{{ range .publish_channels }}{{ if (index . 1).redis_stream }}    ("{{ (index . 1).unique_id }}", {{ if key_exists (index . 1) "channel_bindings" "redis" "maxLen" }}Some({{ (index . 1).channel_bindings.redis.maxLen }}){{ else }}None{{ end }}),
+{{ end }}{{ end }}];
+
+/// connects to the server at `server_url` (e.g. `redis://localhost:6379` or `rediss://` for tls)
+pub async fn connect(server_url: &str) -> RedisResult<Client> {
+    let uri = match server_url.contains("://") {
+        true => server_url.to_string(),
+        false => format!("redis://{}", server_url),
+    };
+    let client = redis::Client::open(uri)?;
+    let connection = client.get_multiplexed_async_connection().await?;
+    info!("Connected to redis server");
+    let streams = STREAMS
+        .iter()
+        .filter_map(|(channel, max_len)| Some((config().channels.get(channel)?.subject.clone(), *max_len)))
+        .collect();
+    Ok(Client {
+        client,
+        connection,
+        streams: Arc::new(streams),
+        replies: Arc::new(OnceCell::new()),
+    })
+}
+
+impl Client {
+    /// the trim length of the stream `subject`, `None` if `subject` is a pub/sub channel
+    fn stream(&self, subject: &str) -> Option<Option<usize>> {
+        self.streams
+            .iter()
+            .find(|(stream, _)| matches(stream, subject))
+            .map(|(_, max_len)| *max_len)
+    }
+
+    async fn subscribe_channel(&self, subject: &str) -> RedisResult<Subscriber> {
+        let (sender, receiver) = mpsc::channel(READ_COUNT);
+        let pattern = subject.contains('{').then(|| glob(subject));
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        match &pattern {
+            Some(pattern) => pubsub.psubscribe(pattern).await?,
+            None => pubsub.subscribe(subject).await?,
+        }
+        info!("Subscribed to {}", pattern.as_deref().unwrap_or(subject));
+        let client = self.client.clone();
+        let subject = subject.to_string();
+        tokio::spawn(async move {
+            loop {
+                let mut messages = pubsub.into_on_message();
+                while let Some(message) = messages.next().await {
+                    let message = IncomingMessage {
+                        subject: message.get_channel_name().to_string(),
+                        reply: None,
+                        payload: message.get_payload_bytes().to_vec(),
+                        headers: None,
+                    };
+                    if sender.send((message, None)).await.is_err() {
+                        return;
+                    }
+                }
+                // messages published while the connection was lost are missed, pub/sub has no history
+                warn!("Subscription of {} lost, subscribing again", subject);
+                pubsub = loop {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    let subscribed = match client.get_async_pubsub().await {
+                        Ok(mut pubsub) => match &pattern {
+                            Some(pattern) => pubsub.psubscribe(pattern).await.map(|_| pubsub),
+                            None => pubsub.subscribe(&subject).await.map(|_| pubsub),
+                        },
+                        Err(e) => Err(e),
+                    };
+                    match subscribed {
+                        Ok(pubsub) => break pubsub,
+                        Err(e) => error!("Failed to subscribe to {} again: {}", subject, e),
+                    }
+                };
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// subscribes to the reply channels of this client, replies are passed to the pending request of their channel
+    async fn subscribe_replies(&self) -> RedisResult<Replies> {
+        let inbox = format!("{{ to_lower (replace .title " " "_") }}:replies:{}", uuid::Uuid::new_v4());
+        let mut subscriber = self.subscribe_channel(&(inbox.clone() + ":{request}")).await?;
+        let pending: Arc<Mutex<HashMap<String, oneshot::Sender<IncomingMessage>>>> = Arc::default();
+        let requests = pending.clone();
+        tokio::spawn(async move {
+            while let Some((reply, _)) = subscriber.recv().await {
+                let request = requests.lock().unwrap().remove(&reply.subject);
+                match request {
+                    Some(request) => {
+                        let _ = request.send(reply);
+                    }
+                    None => debug!("Dropping reply on {}, the request is gone", reply.subject),
+                }
+            }
+        });
+        Ok(Replies { inbox, pending })
+    }
+
+    /// reads the stream `subject` as member of the consumer group, which is created at the end of the stream if missing
+    /// entries left pending by a previous run are read first, entries pending longer than the min idle time
+    /// (unacked by a failed handler or a consumer that is gone) are claimed and delivered again
+    async fn subscribe_stream(&self, subject: &str, consumer: Consumer) -> RedisResult<Subscriber> {
+        if subject.contains('{') {
+            return Err((
+                redis::ErrorKind::InvalidClientConfig,
+                "Streams with channel parameters can not be read",
+                subject.to_string(),
+            )
+                .into());
+        }
+        let (sender, receiver) = mpsc::channel(READ_COUNT);
+        let mut connection = self.client.get_multiplexed_async_connection().await?;
+        let created: RedisResult<()> = connection
+            .xgroup_create_mkstream(subject, &consumer.group, "$")
+            .await;
+        match created {
+            Ok(()) => info!("Created consumer group {} of stream {}", consumer.group, subject),
+            Err(e) if e.code() == Some("BUSYGROUP") => (),
+            Err(e) => return Err(e),
+        }
+        info!("Reading stream {} as {} of group {}", subject, consumer.name, consumer.group);
+        let subject = subject.to_string();
+        tokio::spawn(async move {
+            let options = StreamReadOptions::default()
+                .group(&consumer.group, &consumer.name)
+                .count(READ_COUNT)
+                .block(BLOCK_MS);
+            // ids read the entries pending for this consumer after them, `>` the entries never delivered to the group
+            let mut start = "0".to_string();
+            let mut claim_cursor = "0-0".to_string();
+            let mut last_claim = Instant::now();
+            loop {
+                let mut entries = vec![];
+                if last_claim.elapsed() >= Duration::from_millis(consumer.min_idle_time as u64 / 2) {
+                    last_claim = Instant::now();
+                    let claimed: RedisResult<StreamAutoClaimReply> = connection
+                        .xautoclaim_options(
+                            &subject,
+                            &consumer.group,
+                            &consumer.name,
+                            consumer.min_idle_time,
+                            &claim_cursor,
+                            StreamAutoClaimOptions::default().count(READ_COUNT),
+                        )
+                        .await;
+                    match claimed {
+                        Ok(claimed) => {
+                            if !claimed.claimed.is_empty() {
+                                debug!("Claimed {} pending entries of stream {}", claimed.claimed.len(), subject);
+                            }
+                            claim_cursor = claimed.next_stream_id;
+                            entries.extend(claimed.claimed);
+                        }
+                        Err(e) => error!("Failed to claim pending entries of stream {}: {}", subject, e),
+                    }
+                }
+                // claimed entries are delivered right away, reading blocks until new entries arrive
+                if entries.is_empty() {
+                    let read: RedisResult<StreamReadReply> =
+                        connection.xread_options(&[&subject], &[&start], &options).await;
+                    match read {
+                        Ok(read) => {
+                            entries.extend(read.keys.into_iter().flat_map(|key| key.ids));
+                            match entries.last() {
+                                Some(entry) if start != ">" => start = entry.id.clone(),
+                                None => start = ">".to_string(),
+                                _ => (),
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to read stream {}: {}", subject, e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+                for entry in entries {
+                    if sender.send(Entry::read(&subject, &consumer.group, entry)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(receiver)
+    }
+}
+
+/// whether `subject` is the channel `pattern`, whose `{parameter}` parts match any text
+fn matches(pattern: &str, subject: &str) -> bool {
+    let literals: Vec<&str> = pattern
+        .split('{')
+        .enumerate()
+        .map(|(index, part)| match index {
+            0 => part,
+            _ => part.split_once('}').map(|(_, text)| text).unwrap_or(part),
+        })
+        .collect();
+    let (first, last) = match literals.as_slice() {
+        [_] => return pattern == subject,
+        [first, .., last] => (*first, *last),
+        [] => return false,
+    };
+    let mut rest = match subject.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    // every parameter matches at least one character
+    for literal in &literals[1..literals.len() - 1] {
+        match rest.get(1..).and_then(|tail| tail.find(literal)) {
+            Some(position) => rest = &rest[position + 1 + literal.len()..],
+            None => return false,
+        }
+    }
+    rest.len() > last.len() && rest.ends_with(last)
+}
+
+/// replaces `{parameter}` parts of a channel name with the glob `*` of pattern subscriptions
+fn glob(subject: &str) -> String {
+    subject
+        .split('{')
+        .map(|part| part.split_once('}').map(|(_, text)| format!("*{}", text)).unwrap_or(part.to_string()))
+        .collect()
+}
+
+async fn add_entry(
+    connection: &MultiplexedConnection,
+    stream: &str,
+    max_len: Option<usize>,
+    headers: HeaderMap,
+    reply: Option<&str>,
+    payload: &[u8],
+) -> RedisResult<String> {
+    let mut fields: Vec<(String, Vec<u8>)> = vec![(PAYLOAD_FIELD.to_string(), payload.to_vec())];
+    if let Some(reply) = reply {
+        fields.push((REPLY_FIELD.to_string(), reply.as_bytes().to_vec()));
+    }
+    fields.extend(headers.0.into_iter().map(|(key, value)| (key, value.into_bytes())));
+    let mut connection = connection.clone();
+    match max_len {
+        Some(max_len) => connection.xadd_maxlen(stream, StreamMaxlen::Approx(max_len), "*", &fields).await,
+        None => connection.xadd(stream, "*", &fields).await,
+    }
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscriber;
+    /// the stream entry to acknowledge, pub/sub messages need no acknowledgement
+    type Delivery = Option<Entry>;
+
+    /// adds an entry to stream channels, with `headers` as further fields, and publishes to pub/sub channels, which drop `headers`
+    async fn publish(&self, _channel: &str, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        match self.stream(subject) {
+            Some(max_len) => {
+                let id = add_entry(&self.connection, subject, max_len, headers, None, payload).await?;
+                debug!("Added entry {} to stream {}", id, subject);
+            }
+            None => {
+                if !headers.0.is_empty() {
+                    debug!("Dropping headers of message to {}, pub/sub messages have no headers", subject);
+                }
+                let receivers: usize = self.connection.clone().publish(subject, payload).await?;
+                debug!("Published message to {} subscribers of {}", receivers, subject);
+            }
+        }
+        Ok(())
+    }
+
+    /// subscribes to `subject`, the subject of `channel`
+    /// pub/sub channels with parameters like `{userId}` are subscribed as pattern,
+    /// streams are read with the consumer group of the channel
+    async fn subscribe(&self, channel: &str, subject: &str) -> anyhow::Result<Subscriber> {
+        let stream = STREAMS.iter().any(|(stream, _)| *stream == channel);
+        let subscriber = match config().channels.get(channel) {
+            Some(settings) if stream => self.subscribe_stream(subject, Consumer::of(settings)).await?,
+            _ => self.subscribe_channel(subject).await?,
+        };
+        Ok(subscriber)
+    }
+
+    async fn next(&self, subscription: &mut Subscriber) -> Option<(IncomingMessage, Option<Entry>)> {
+        subscription.recv().await
+    }
+
+    /// handled and terminated stream entries are acknowledged, nacked ones stay pending
+    /// and are delivered again after the min idle time of the channel
+    async fn ack(&self, delivery: Option<Entry>, settlement: Settlement) -> anyhow::Result<()> {
+        if let (Some(entry), Settlement::Ack | Settlement::Term) = (delivery, settlement) {
+            let _: usize = self.connection.clone().xack(&entry.stream, &entry.group, &[&entry.id]).await?;
+        }
+        Ok(())
+    }
+
+    /// adds a request entry with a `reply_to` field to the stream `subject` and waits for the reply on that pub/sub channel,
+    /// the reply channels of all requests share one pattern subscription,
+    /// fails if no reply arrives within `REQUEST_TIMEOUT_MS`, pub/sub channels have no field to name the reply channel
+    async fn request(&self, subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        let max_len = match self.stream(subject) {
+            Some(max_len) => max_len,
+            None => return Err(anyhow::anyhow!("Requests on {} need a stream channel", subject)),
+        };
+        let timeout = config().request_timeout;
+        let replies = self.replies.get_or_try_init(|| self.subscribe_replies()).await?;
+        let (sender, receiver) = oneshot::channel();
+        let pending = PendingReply {
+            channel: format!("{}:{}", replies.inbox, uuid::Uuid::new_v4()),
+            replies,
+        };
+        replies.pending.lock().unwrap().insert(pending.channel.clone(), sender);
+        add_entry(&self.connection, subject, max_len, headers, Some(&pending.channel), payload).await?;
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(reply)) => {
+                debug!("Received reply on {}", pending.channel);
+                Ok(reply)
+            }
+            Ok(Err(_)) => Err(anyhow::anyhow!("Subscription of {} closed", pending.channel)),
+            Err(_) => Err(anyhow::anyhow!("Request on {} timed out after {:?}", subject, timeout)),
+        }
+    }
+
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.publish("", reply_subject, headers, payload).await
+    }
+}
+{{ end }}
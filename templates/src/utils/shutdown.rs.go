@@ -0,0 +1,54 @@
+use crate::config::config;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// tells the listeners to stop receiving messages and to finish the ones they handle within `SHUTDOWN_TIMEOUT_MS`
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+/// starts the shutdown of the listeners
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl Shutdown {
+    pub fn new() -> (ShutdownTrigger, Shutdown) {
+        let (sender, receiver) = watch::channel(false);
+        (ShutdownTrigger(sender), Shutdown { receiver })
+    }
+
+    /// resolves once the shutdown was triggered, never if the trigger was dropped before
+    pub async fn triggered(&mut self) {
+        while !*self.receiver.borrow_and_update() {
+            if self.receiver.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// how long the handlers get to finish their messages, `SHUTDOWN_TIMEOUT_MS` (20000)
+    pub fn timeout(&self) -> Duration {
+        config().shutdown_timeout
+    }
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// resolves on SIGINT (ctrl-c) or, on unix, SIGTERM as sent by kubernetes and docker
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
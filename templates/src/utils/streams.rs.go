@@ -1,49 +1,136 @@
+{{ if eq .protocol "nats" }}
+use super::set_consumer_pending;
+use crate::config::JetStreamConfig;
+use async_nats::jetstream::consumer::{pull, push, Consumer, IntoConsumerConfig};
+use async_nats::jetstream::stream;
 use async_nats::jetstream::{self, Context};
-use async_nats::Client;
-use async_nats::jetstream::consumer::{pull::{Config}, Consumer};
-use std::time::Duration;
 use futures::StreamExt;
-use log::debug;
+use log::{debug, error, info};
+use std::time::Duration;
 
-pub async fn stream_publish_message(client: &Context, channel: &str, payload: &str) {
-	let owned_payload = payload.to_owned().into(); // Convert to Bytes
-	client
-		.publish(channel.to_string(), owned_payload)
-		.await
-		.unwrap();
-	debug!("Message published to channel: {}", channel);
+/// the messages of a jetstream consumer, fetched by long polls of a pull consumer or sent to an inbox by a push consumer
+pub enum StreamMessages {
+    Pull(pull::Stream),
+    Push(push::Messages),
 }
 
-
-pub async fn stream_listen_for_message(
-    sub: &Consumer<Config>,
-    handler: impl Fn(jetstream::Message, &Client),
-    client: &Client
-) -> Result<(), async_nats::Error> {
-    loop {
-        tokio::time::sleep(Duration::from_millis(1000)).await;
-        let mut messages = sub.messages().await?.take(10);
-        while let Some(message) = messages.next().await {
-            let message = message?;
-            handler(message, client);
-            debug!(
-                "Message received by Subscriber: {:?}",
-                sub.cached_info().name
-            ); // if you show sub its a mess, is now a Context
+impl StreamMessages {
+    /// waits for the next message, `None` once the consumer is gone
+    pub async fn next(&mut self) -> Option<jetstream::Message> {
+        loop {
+            let message = match self {
+                StreamMessages::Pull(messages) => messages.next().await?.map_err(|e| e.to_string()),
+                StreamMessages::Push(messages) => messages.next().await?.map_err(|e| e.to_string()),
+            };
+            match message {
+                Ok(message) => return Some(message),
+                Err(e) => error!("Failed to receive stream message: {}", e),
+            }
         }
     }
 }
 
+/// the stream `stream_name`, created with `subject` and the `{channel}_STREAM_*` settings if it does not exist
+async fn get_stream(
+    jetstream: &Context,
+    settings: &JetStreamConfig,
+    stream_name: &str,
+    subject: &str,
+) -> Result<stream::Stream, async_nats::Error> {
+    let stream = jetstream
+        .get_or_create_stream(stream::Config {
+            name: stream_name.to_string(),
+            subjects: vec![subject.to_string()],
+            retention: settings.retention,
+            storage: settings.storage,
+            max_age: settings.max_age,
+            max_messages: settings.max_messages,
+            max_bytes: settings.max_bytes,
+            ..Default::default()
+        })
+        .await?;
+    Ok(stream)
+}
 
-pub async fn get_consumer(jetstream: &Context, stream_name: &str) -> Result<Consumer<Config>, async_nats::Error>{
+/// polls the messages `consumer` has not delivered yet every 5 seconds for the `{channel}_consumer_pending` metric
+fn watch_pending<T>(channel: &str, mut consumer: Consumer<T>)
+where
+    T: IntoConsumerConfig + Send + 'static,
+{
+    let channel = channel.to_string();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            match consumer.info().await {
+                Ok(info) => set_consumer_pending(&channel, info.num_pending),
+                Err(e) => debug!("Failed to get the consumer info of {}: {}", channel, e),
+            }
+        }
+    });
+}
 
-    let stream = jetstream.get_or_create_stream(jetstream::stream::Config {
-        name: stream_name.to_string(),
-        ..Default::default()
-    }).await?;
-    let consumer = stream.get_or_create_consumer("consumer", Config {
-        durable_name: Some("consumer".to_string()),
-        ..Default::default()
-    }).await?;
-    return Ok(consumer);
-}
\ No newline at end of file
+/// reads the stream `stream_name` with the durable consumer of `settings`, created or updated with them,
+/// the consumer pulls its messages unless its mode is push
+pub async fn consume(
+    client: &async_nats::Client,
+    channel: &str,
+    settings: &JetStreamConfig,
+    stream_name: &str,
+    subject: &str,
+) -> Result<StreamMessages, async_nats::Error> {
+    let stream = get_stream(&jetstream::new(client.clone()), settings, stream_name, subject).await?;
+    let name = settings.consumer.clone();
+    let mut filter_subjects = settings.filter_subjects.clone();
+    // servers before 2.10 only know a single filter subject
+    let filter_subject = match filter_subjects.len() {
+        1 => filter_subjects.remove(0),
+        _ => String::new(),
+    };
+    if settings.push {
+        let consumer = stream
+            .create_consumer(push::Config {
+                deliver_subject: client.new_inbox(),
+                durable_name: Some(name.clone()),
+                deliver_policy: settings.deliver_policy,
+                ack_policy: settings.ack_policy,
+                ack_wait: settings.ack_wait,
+                max_deliver: settings.max_deliver,
+                filter_subject,
+                filter_subjects,
+                flow_control: true,
+                idle_heartbeat: Duration::from_secs(5),
+                ..Default::default()
+            })
+            .await?;
+        watch_pending(channel, consumer.clone());
+        info!(
+            "Receiving messages of stream {} with push consumer {}",
+            stream_name, name
+        );
+        return Ok(StreamMessages::Push(consumer.messages().await?));
+    }
+    let consumer = stream
+        .create_consumer(pull::Config {
+            durable_name: Some(name.clone()),
+            deliver_policy: settings.deliver_policy,
+            ack_policy: settings.ack_policy,
+            ack_wait: settings.ack_wait,
+            max_deliver: settings.max_deliver,
+            filter_subject,
+            filter_subjects,
+            ..Default::default()
+        })
+        .await?;
+    watch_pending(channel, consumer.clone());
+    let mut messages = consumer.stream();
+    if let Some(batch) = settings.pull_batch {
+        messages = messages.max_messages_per_batch(batch);
+    }
+    if let Some(expires) = settings.pull_expires {
+        messages = messages.expires(expires);
+    }
+    info!("Pulling messages of stream {} with consumer {}", stream_name, name);
+    Ok(StreamMessages::Pull(messages.messages().await?))
+}
+{{ end }}
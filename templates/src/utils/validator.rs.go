@@ -4,14 +4,9 @@ pub fn validate_message_schema(
     validator_path: &std::path::Path,
     instance: &serde_json::Value,
 ) -> Result<(), String> {
-    match std::env::var("SCHEMA_VALIDATION_ENABLED") {
-        Ok(enabled) => {
-            if enabled == "false" {
-                return Ok(());
-            }
-        }
-        Err(_) => return Ok(()),
-    };
+    if !crate::config::config().schema_validation_enabled {
+        return Ok(());
+    }
     // read json schema file as json value
     let schema_source = match std::fs::read(validator_path){
         Ok(schema) => schema,
@@ -0,0 +1,402 @@
+{{ if eq .protocol "ws" }}
+use super::{IncomingMessage, MessageBroker, Settlement};
+use crate::config::config;
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message as Frame;
+use warp::filters::path::FullPath;
+use warp::ws::{WebSocket, Ws};
+use warp::Filter;
+
+/// reply subjects of messages name the connection they were received on, replies are sent back over it
+const PEER_PREFIX: &str = "ws-peer:";
+
+/// receives the frames of all connections whose path matches the subscribed channel
+pub type Subscriber = mpsc::UnboundedReceiver<IncomingMessage>;
+
+/// websocket frames have no headers, handshake headers are configured per channel
+#[derive(Clone, Debug, Default)]
+pub struct HeaderMap(BTreeMap<String, String>);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// an open connection, frames sent to `sender` are written to the socket
+struct Peer {
+    id: u64,
+    path: String,
+    sender: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    peers: Vec<Peer>,
+    subscriptions: Vec<(String, mpsc::UnboundedSender<IncomingMessage>)>,
+}
+
+/// the connections of the service, either accepted by the warp server or opened to `SERVER_URL`
+#[derive(Clone)]
+pub struct Client {
+    /// `None` in server mode
+    server_url: Option<String>,
+    connections: Arc<Mutex<Connections>>,
+}
+
+/// the channels of the spec, connections to other paths are refused
+const CHANNELS: &[&str] = &[{{ range .subscribe_channels }}"{{ (index . 1).unique_id }}", {{ end }}{{ range .publish_channels }}"{{ (index . 1).unique_id }}", {{ end }}];
+
+/// `WS_MODE = "server"` accepts connections on the channel paths of the warp server,
+/// `WS_MODE = "client"` connects to the channel paths of `server_url` instead
+pub async fn connect(server_url: &str) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
+    let server_url = match config().ws.client_mode {
+        true => Some(server_url.trim_end_matches('/').to_string()),
+        false => None,
+    };
+    let client = Client {
+        server_url,
+        connections: Arc::new(Mutex::new(Connections::default())),
+    };
+    if client.server_url.is_some() {
+        for channel in CHANNELS {
+            let Some(settings) = config().channels.get(channel) else {
+                continue;
+            };
+            let path = settings.subject.clone();
+            if path.contains('{') {
+                warn!("Not connecting to {}, replace its parameters in {}_SUBJECT", path, channel);
+                continue;
+            }
+            // the publish and subscribe operation of a channel share its connection
+            if client.connections.lock().unwrap().peers.iter().any(|peer| peer.path == path) {
+                continue;
+            }
+            client.open(&path, settings.query.clone(), settings.headers.clone());
+        }
+    }
+    Ok(client)
+}
+
+impl Client {
+    // registers a connection and returns its id and the receiver of the frames to write
+    fn add_peer(&self, path: &str) -> (u64, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut connections = self.connections.lock().unwrap();
+        connections.next_id += 1;
+        let id = connections.next_id;
+        connections.peers.push(Peer {
+            id,
+            path: path.to_string(),
+            sender,
+        });
+        (id, receiver)
+    }
+
+    fn remove_peer(&self, id: u64) {
+        self.connections.lock().unwrap().peers.retain(|peer| peer.id != id);
+    }
+
+    fn dispatch(&self, id: u64, path: &str, payload: Vec<u8>) {
+        // the subject of a frame is the path of its connection, the reply subject the connection itself
+        let message = IncomingMessage {
+            subject: path.to_string(),
+            reply: Some(format!("{}{}", PEER_PREFIX, id)),
+            payload,
+            headers: None,
+        };
+        let connections = self.connections.lock().unwrap();
+        let mut subscribed = false;
+        for (pattern, sender) in connections.subscriptions.iter() {
+            if path_matches(pattern, path) {
+                subscribed = true;
+                if sender.send(message.clone()).is_err() {
+                    warn!("Subscriber of {} is gone", pattern);
+                }
+            }
+        }
+        if !subscribed {
+            debug!("Dropping frame received on {}, no channel is subscribed", path);
+        }
+    }
+
+    /// client mode: connects to `path` of the server in the background, frames sent before the connection is up are buffered
+    /// lost connections are reopened with exponential backoff up to `WS_MAX_BACKOFF_MS`
+    fn open(&self, path: &str, query: Option<String>, headers: Option<String>) -> mpsc::UnboundedSender<Vec<u8>> {
+        let (id, mut outgoing) = self.add_peer(path);
+        let sender = self.peer_sender(id).expect("peer was just added");
+        let url = match query {
+            Some(query) if !query.is_empty() => format!("{}{}?{}", self.server_url.clone().unwrap_or_default(), path, query),
+            _ => format!("{}{}", self.server_url.clone().unwrap_or_default(), path),
+        };
+        let max_backoff = config().ws.max_backoff;
+        let client = self.clone();
+        let path = path.to_string();
+        tokio::spawn(async move {
+            let mut backoff = Duration::from_millis(500);
+            loop {
+                let mut request = match url.as_str().into_client_request() {
+                    Ok(request) => request,
+                    Err(e) => {
+                        error!("Invalid websocket url {}: {}", url, e);
+                        client.remove_peer(id);
+                        return;
+                    }
+                };
+                for (name, value) in headers.iter().flat_map(|headers| headers.split('&')).filter_map(|header| header.split_once('=')) {
+                    let (name, value) = (percent_decode(name), percent_decode(value));
+                    match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+                        (Ok(name), Ok(value)) => {
+                            request.headers_mut().insert(name, value);
+                        }
+                        _ => warn!("Skipping invalid handshake header {}", name),
+                    }
+                }
+                let socket = match tokio_tungstenite::connect_async(request).await {
+                    Ok((socket, _)) => socket,
+                    Err(e) => {
+                        error!("Failed to connect to {}, retrying in {:?}: {}", url, backoff, e);
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(max_backoff);
+                        continue;
+                    }
+                };
+                info!("Connected to {}", url);
+                backoff = Duration::from_millis(500);
+                let (mut write, mut read) = socket.split();
+                loop {
+                    tokio::select! {
+                        frame = read.next() => match frame {
+                            Some(Ok(Frame::Text(text))) => client.dispatch(id, &path, text.into_bytes()),
+                            Some(Ok(Frame::Binary(data))) => client.dispatch(id, &path, data),
+                            Some(Ok(Frame::Close(_))) | None => break,
+                            Some(Ok(_)) => (),
+                            Some(Err(e)) => {
+                                error!("Connection to {} failed: {}", url, e);
+                                break;
+                            }
+                        },
+                        payload = outgoing.recv() => match payload {
+                            Some(payload) => {
+                                if let Err(e) = write.send(frame(payload)).await {
+                                    error!("Failed to send frame to {}: {}", url, e);
+                                    break;
+                                }
+                            }
+                            None => return,
+                        },
+                    }
+                }
+                warn!("Connection to {} closed, reconnecting in {:?}", url, backoff);
+                tokio::time::sleep(backoff).await;
+            }
+        });
+        sender
+    }
+
+    fn peer_sender(&self, id: u64) -> Option<mpsc::UnboundedSender<Vec<u8>>> {
+        self.connections
+            .lock()
+            .unwrap()
+            .peers
+            .iter()
+            .find(|peer| peer.id == id)
+            .map(|peer| peer.sender.clone())
+    }
+
+    /// senders of all connections on `subject`, which is either a path or the reply subject of a received message
+    fn senders(&self, subject: &str) -> Vec<mpsc::UnboundedSender<Vec<u8>>> {
+        if let Some(id) = subject.strip_prefix(PEER_PREFIX).and_then(|id| id.parse().ok()) {
+            return self.peer_sender(id).into_iter().collect();
+        }
+        let senders: Vec<mpsc::UnboundedSender<Vec<u8>>> = self
+            .connections
+            .lock()
+            .unwrap()
+            .peers
+            .iter()
+            .filter(|peer| path_matches(subject, &peer.path))
+            .map(|peer| peer.sender.clone())
+            .collect();
+        match (senders.is_empty(), &self.server_url) {
+            (true, Some(_)) if !subject.contains('{') => vec![self.open(subject, None, None)],
+            _ => senders,
+        }
+    }
+}
+
+// text frames for utf-8 payloads, binary frames otherwise
+fn frame(payload: Vec<u8>) -> Frame {
+    match String::from_utf8(payload) {
+        Ok(text) => Frame::Text(text),
+        Err(e) => Frame::Binary(e.into_bytes()),
+    }
+}
+
+/// `{parameter}` segments of `pattern` match any segment of `path`
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    pattern.len() == path.len()
+        && pattern
+            .iter()
+            .zip(path.iter())
+            .all(|(expected, segment)| expected == segment || (expected.starts_with('{') && expected.ends_with('}')))
+}
+
+/// server mode: upgrades requests to the channel paths to websocket connections, served by the warp server
+/// without client (in-memory broker) or in client mode all upgrades are refused
+pub fn websocket_route(
+    client: Option<Client>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and(warp::ws())
+        .and_then(move |path: FullPath, ws: Ws| {
+            let client = client.clone();
+            async move {
+                let known = CHANNELS.iter().any(|channel| {
+                    config().channels.get(channel).is_some_and(|settings| path_matches(&settings.subject, path.as_str()))
+                });
+                match client {
+                    Some(client) if known && client.server_url.is_none() => {
+                        Ok(ws.on_upgrade(move |socket| serve_connection(client, path.as_str().to_string(), socket)))
+                    }
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        })
+}
+
+async fn serve_connection(client: Client, path: String, socket: WebSocket) {
+    let (id, mut outgoing) = client.add_peer(&path);
+    info!("Accepted connection {} on {}", id, path);
+    let (mut write, mut read) = socket.split();
+    loop {
+        tokio::select! {
+            frame = read.next() => match frame {
+                Some(Ok(frame)) if frame.is_text() || frame.is_binary() => client.dispatch(id, &path, frame.into_bytes()),
+                Some(Ok(frame)) if frame.is_close() => break,
+                Some(Ok(_)) => (),
+                Some(Err(e)) => {
+                    error!("Connection {} on {} failed: {}", id, path, e);
+                    break;
+                }
+                None => break,
+            },
+            payload = outgoing.recv() => match payload {
+                Some(payload) => {
+                    let frame = match String::from_utf8(payload) {
+                        Ok(text) => warp::ws::Message::text(text),
+                        Err(e) => warp::ws::Message::binary(e.into_bytes()),
+                    };
+                    if let Err(e) = write.send(frame).await {
+                        error!("Failed to send frame to connection {}: {}", id, e);
+                        break;
+                    }
+                }
+                None => break,
+            },
+        }
+    }
+    client.remove_peer(id);
+    info!("Connection {} on {} closed", id, path);
+}
+
+#[async_trait]
+impl MessageBroker for Client {
+    type Subscription = Subscriber;
+    /// frames need no acknowledgement
+    type Delivery = ();
+
+    /// sends the payload to every connection on the path `subject`, in client mode the connection is opened if needed
+    /// websocket frames have no headers, they are dropped
+    async fn publish(&self, _channel: &str, subject: &str, _headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        let senders = self.senders(subject);
+        if senders.is_empty() {
+            debug!("No connection on {}, dropping message", subject);
+        }
+        for sender in senders {
+            if sender.send(payload.to_vec()).is_err() {
+                warn!("Connection on {} closed before the message was sent", subject);
+            }
+        }
+        Ok(())
+    }
+
+    /// dispatches the frames received on connections whose path matches `subject` to the subscriber
+    /// channel parameters like `{symbol}` match any path segment
+    async fn subscribe(&self, _channel: &str, subject: &str) -> anyhow::Result<Subscriber> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.connections
+            .lock()
+            .unwrap()
+            .subscriptions
+            .push((subject.to_string(), sender));
+        debug!("Subscribed to {}", subject);
+        Ok(receiver)
+    }
+
+    async fn next(&self, subscription: &mut Subscriber) -> Option<(IncomingMessage, ())> {
+        subscription.recv().await.map(|message| (message, ()))
+    }
+
+    async fn ack(&self, _delivery: (), _settlement: Settlement) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// websockets have no request/reply, requests are answered over the connection they were received on
+    async fn request(&self, subject: &str, _headers: HeaderMap, _payload: &[u8]) -> anyhow::Result<IncomingMessage> {
+        Err(anyhow::anyhow!("Requests on {} are not supported by the websocket backend", subject))
+    }
+
+    /// sends the reply over the connection the request was received on, headers are dropped
+    async fn reply(&self, reply_subject: &str, headers: HeaderMap, payload: &[u8]) -> anyhow::Result<()> {
+        self.publish("", reply_subject, headers, payload).await
+    }
+}
+
+/// decodes the percent-encoded names and values of `{channel}_HEADERS`
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+{{ end }}
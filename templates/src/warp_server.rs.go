@@ -1,32 +1,54 @@
-use cargo_metadata::MetadataCommand;
+use warp::http::StatusCode;
 use warp::Filter;
-use serde_json;
+use serde_json::json;
 
-use crate::config::get_env;
+use crate::config::config;
+use crate::utils::{liveness, readiness, render_metrics};
 
+/// fails once a handler is stuck, see `LIVENESS_HANDLER_TIMEOUT_MS`
 async fn life() -> Result<impl warp::Reply, warp::Rejection> {
-    Ok(format!("alive\n"))
+    match liveness() {
+        Ok(()) => Ok(warp::reply::with_status("alive\n".to_string(), StatusCode::OK)),
+        Err(reason) => Ok(warp::reply::with_status(format!("{}\n", reason), StatusCode::SERVICE_UNAVAILABLE)),
+    }
 }
 
+/// ready while connected to the broker and listening on every channel, not ready during shutdown
 async fn ready_func() -> Result<impl warp::Reply, warp::Rejection> {
-    //TODO: actually check if service is ready
-    Ok(format!("ready\n"))
+    match readiness() {
+        Ok(()) => Ok(warp::reply::with_status("ready\n".to_string(), StatusCode::OK)),
+        Err(reason) => Ok(warp::reply::with_status(format!("{}\n", reason), StatusCode::SERVICE_UNAVAILABLE)),
+    }
 }
 
-async fn metamessage() -> Result<impl warp::Reply, warp::Rejection>{
-    let path = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    let meta = MetadataCommand::new()
-        .manifest_path("./Cargo.toml")
-        .current_dir(&path)
-        .exec()
-        .unwrap();
-
-    let root = meta.root_package().unwrap();
-    let root = serde_json::to_string(&root).unwrap();
-    Ok(root)
+/// the build of the service, `GIT_COMMIT` is set by the build script
+async fn metamessage() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": env!("GIT_COMMIT"),
+        "protocol": "{{ .protocol }}",
+        "spec": {
+            "title": "{{ .title }}",
+            "version": "{{ .version }}",
+        },
+    })))
 }
 
+/// the metrics of the channels in the prometheus text format
+async fn metrics() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(
+        render_metrics(),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+{{ if eq .protocol "ws" }}
+pub async fn server(client: Option<crate::utils::Client>) {
+{{ else }}
 pub async fn server() {
+{{ end }}
 
     let metadata = warp::get()
         .and(warp::path("root"))
@@ -34,6 +56,11 @@ pub async fn server() {
         .and_then(metamessage);
 
 
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and_then(metrics);
+
     let liveness = warp::get()
         .and(warp::path("healthz"))
         .and(warp::path::end())
@@ -44,15 +71,14 @@ pub async fn server() {
         .and(warp::path::end())
         .and_then(ready_func);
 
-    let port = match get_env("SERVICE_PORT") {
-        Some(port) => match port.parse::<u16>() {
-            Ok(port) => port,
-            Err(_) => panic!("SERVICE_PORT is not a valid port number"),
-        }
-        None => panic!("SERVICE_PORT not set"),
-    };
+    let port = config().service_port;
 
-    warp::serve(liveness.or(readiness).or(metadata))
+    {{ if eq .protocol "ws" }}
+    let routes = liveness.or(readiness).or(metadata).or(metrics).or(crate::utils::websocket_route(client));
+    {{ else }}
+    let routes = liveness.or(readiness).or(metadata).or(metrics);
+    {{ end }}
+    warp::serve(routes)
         .run(([127, 0, 0, 1], port))
         .await;
 }
\ No newline at end of file